@@ -0,0 +1 @@
+pub mod youtube_manager;
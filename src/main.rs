@@ -1,26 +1,57 @@
-mod youtube_manager;
-
-use clap::{App, Arg, SubCommand};
+use chrono::{SecondsFormat, Utc};
+use clap::{App, AppSettings, Arg, SubCommand};
 use env_logger;
 use env_logger::Logger;
-use google_youtube3::{Result, YouTube};
+use google_youtube3::{api::Scope, YouTube};
 use hyper;
+use hyper::client::HttpConnector;
 use hyper_rustls;
+use hyper_rustls::HttpsConnector;
 use log::debug;
+use playlist_manager::youtube_manager::{
+    self,
+    config::Config,
+    encryption::Encryption,
+    error::{Error, Result},
+    export::{self, ExportFormat},
+    output::OutputFormat,
+    plan::Plan,
+    playlist::Playlist,
+    profile::{Profiles, DEFAULT_PROFILE},
+    quota::{self, Quota},
+    retry::{self, RetryPolicy},
+    snapshot::Snapshot,
+    sort::SortPolicy,
+    tokens::{TokenCache, LEGACY_TOKEN_CACHE},
+    watch::Watcher,
+};
+use std::fs;
 use std::future::Future;
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
+use std::time::Duration;
 use tokio;
 use tokio::io::AsyncBufReadExt;
+use tokio::signal;
+use tokio::time::MissedTickBehavior;
 use webbrowser;
-use youtube_manager::playlist::Playlist;
 use yup_oauth2::{
-    authenticator_delegate::InstalledFlowDelegate, read_application_secret,
+    authenticator::Authenticator,
+    authenticator_delegate::{DeviceAuthResponse, DeviceFlowDelegate, InstalledFlowDelegate},
+    read_application_secret, ApplicationSecret, DeviceFlowAuthenticator,
     InstalledFlowAuthenticator, InstalledFlowReturnMethod,
 };
 
 const DELAY: u64 = 5;
 
-fn main() -> Result<()> {
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("Error: {}", e);
+        std::process::exit(e.exit_code());
+    }
+}
+
+fn run() -> Result<()> {
     let logger = Logger::from_default_env();
     async_log::Logger::wrap(logger, || 12)
         .start(log::LevelFilter::Trace)
@@ -28,17 +59,43 @@ fn main() -> Result<()> {
     let matches = App::new("playlist-manager")
         .arg(
             Arg::with_name("playlist_id")
-                .help("A playlist id")
-                .index(1) // Starts at 1
-                .required(true),
+                .help("A playlist id. Required except with the auth subcommand.")
+                .index(1), // Starts at 1
         )
         .arg(
             Arg::with_name("client")
-                .help("Path to YouTube client id file")
+                .help("Path to YouTube client id file. Required except with auth list and auth logout.")
                 .long_help("Path to YouTube client id file. See https://github.com/glyn/youtube-playlist-manager for how to create this.")
                 .takes_value(true)
-                .long("client")
-                .required(true),
+                .long("client"),
+        )
+        .arg(
+            Arg::with_name("profile")
+                .help("Name of the profile whose cached tokens are used, so that several accounts can be used side by side")
+                .takes_value(true)
+                .long("profile")
+                .default_value(DEFAULT_PROFILE),
+        )
+        .arg(
+            Arg::with_name("token cache")
+                .help("File in which tokens are cached, instead of the profile given by --profile")
+                .takes_value(true)
+                .long("token-cache")
+                .env("PLAYLIST_MANAGER_TOKEN_CACHE"),
+        )
+        .arg(
+            Arg::with_name("auth flow")
+                .help("How to authorise access to YouTube. device prints a code to enter on another device, for machines without a browser.")
+                .takes_value(true)
+                .long("auth-flow")
+                .possible_values(&["installed", "device"])
+                .default_value("installed"),
+        )
+        .arg(
+            Arg::with_name("no browser")
+                .help("Prints the authorisation link instead of launching a browser")
+                .long("no-browser")
+                .takes_value(false),
         )
         .arg(
             Arg::with_name("timezone")
@@ -47,6 +104,47 @@ fn main() -> Result<()> {
                 .long("timezone")
                 .default_value(""),
         )
+        .arg(
+            Arg::with_name("output")
+                .help("Output format. json and jsonl print playlist items and changes as records on standard output.")
+                .takes_value(true)
+                .long("output")
+                .possible_values(&["text", "json", "jsonl"])
+                .default_value("text"),
+        )
+        .arg(
+            Arg::with_name("quota budget")
+                .help("Maximum number of YouTube API quota units to use, or with watch to use in each check. Changes which would exceed this are not made.")
+                .takes_value(true)
+                .long("quota-budget"),
+        )
+        .arg(
+            Arg::with_name("journal")
+                .help("File in which the progress of changes to the playlist is recorded so that an interrupted run can be resumed. Defaults to playlist-manager-journal-<playlist_id>.json.")
+                .takes_value(true)
+                .long("journal"),
+        )
+        .arg(
+            Arg::with_name("max retries")
+                .help("Maximum number of times a YouTube API call which fails with a transient error is retried")
+                .takes_value(true)
+                .long("max-retries")
+                .default_value("5"),
+        )
+        .arg(
+            Arg::with_name("retry delay")
+                .help("Delay before the first retry of a failed YouTube API call, e.g. 500ms or 2s. Later retries wait twice as long as the one before.")
+                .takes_value(true)
+                .long("retry-delay")
+                .default_value("1s"),
+        )
+        .arg(
+            Arg::with_name("max retry delay")
+                .help("Maximum delay before a retry of a failed YouTube API call")
+                .takes_value(true)
+                .long("max-retry-delay")
+                .default_value("32s"),
+        )
         .arg(
             Arg::with_name("debug")
                 .help("Prints extra debugging information")
@@ -61,18 +159,59 @@ fn main() -> Result<()> {
         .subcommand(
      SubCommand::with_name("sort")
                 .about("Sorts, and optionally prunes, the playlist")
+                .args(&sort_args())
                 .arg(
-                    Arg::with_name("prune")
-                        .help("Removes extraneous entries from the playlist")
-                        .long("prune")
-                        .takes_value(false),
-                )
+                    Arg::with_name("plan out")
+                        .help("Saves the changes to the given file, for applying later, instead of making them")
+                        .long("plan-out")
+                        .takes_value(true)
+                        .conflicts_with("update"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("watch")
+                .about("Keeps running and sorts, and optionally prunes, the playlist whenever it changes")
+                .args(&sort_args())
                 .arg(
-                    Arg::with_name("max playable")
-                        .help("Maximum number of playable videos in the playlist. Others may be pruned.")
-                        .long("max-playable")
+                    Arg::with_name("interval")
+                        .help("Time between checks of the playlist, e.g. 30s, 10m, or 1h")
+                        .long("interval")
                         .takes_value(true)
-                        .default_value("6"),
+                        .default_value("10m"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("apply")
+                .about("Makes the changes in a plan saved by sort --plan-out, provided the playlist has not changed since")
+                .arg(
+                    Arg::with_name("plan")
+                        .help("Path to the plan file")
+                        .index(1)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("resume")
+                .about("Finishes changing the playlist after a sort, prune, apply, restore, or reconcile which was interrupted"),
+        )
+        .subcommand(
+            SubCommand::with_name("snapshot")
+                .about("Saves the contents of the playlist to a file")
+                .arg(
+                    Arg::with_name("file")
+                        .help("Path to the snapshot file")
+                        .index(1)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("restore")
+                .about("Restores the playlist to the contents saved by the snapshot command, re-inserting any removed videos")
+                .arg(
+                    Arg::with_name("file")
+                        .help("Path to the snapshot file")
+                        .index(1)
+                        .required(true),
                 )
                 .arg(
                     Arg::with_name("update")
@@ -81,105 +220,610 @@ fn main() -> Result<()> {
                         .long("update"),
                 ),
         )
+        .subcommand(
+            SubCommand::with_name("reconcile")
+                .about("Changes the playlist to match the desired state described in a config file")
+                .arg(
+                    Arg::with_name("config")
+                        .help("Path to the config file")
+                        .index(1)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("archive to")
+                        .help("Adds pruned videos to the playlist with the given id before removing them")
+                        .long("archive-to")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("update")
+                        .help("Update YouTube")
+                        .takes_value(false)
+                        .long("update"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("Prints the playlist as a table, a calendar of scheduled streams, or a feed")
+                .arg(
+                    Arg::with_name("format")
+                        .help("Export format")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(&["csv", "markdown", "ics", "atom", "rss"])
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("out")
+                        .help("Writes the export to the given file instead of printing it")
+                        .long("out")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("auth")
+                .about("Manages the profiles in which tokens for accessing YouTube are cached")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(SubCommand::with_name("list").about("Lists the profiles"))
+                .subcommand(
+                    SubCommand::with_name("login")
+                        .about("Authorises access to YouTube and caches the tokens in the profile given by --profile"),
+                )
+                .subcommand(
+                    SubCommand::with_name("logout")
+                        .about("Deletes the profile given by --profile, together with its cached tokens"),
+                )
+                .subcommand(
+                    SubCommand::with_name("status")
+                        .about("Checks, without asking for authorisation, that the tokens cached in the profile given by --profile are valid"),
+                ),
+        )
         .get_matches();
 
-    let mut sort = false;
-    let mut prune = false;
-    let mut max_playable = 6;
+    let auth_flow = match matches.value_of("auth flow").unwrap() {
+        "device" => AuthFlow::Device,
+        _ => AuthFlow::Installed {
+            browser: !matches.is_present("no browser"),
+        },
+    };
+    let profile = matches.value_of("profile").unwrap();
+    if let ("auth", Some(sub_matches)) = matches.subcommand() {
+        return runtime()?.block_on(auth(
+            sub_matches,
+            matches.value_of("token cache"),
+            profile,
+            matches.value_of("client"),
+            auth_flow,
+        ));
+    }
+
+    let mut command = Command::Print;
     let mut dry_run = true;
 
     match matches.subcommand() {
-        (_, Some(sub_matches)) => {
-            sort = true;
-            max_playable = sub_matches
-                .value_of("max playable")
-                .unwrap()
-                .to_string()
-                .parse::<usize>()
-                .unwrap();
-            prune = sub_matches.is_present("prune");
+        ("sort", Some(sub_matches)) => {
+            command = Command::Sort {
+                prune: sub_matches.is_present("prune"),
+                plan_out: sub_matches.value_of("plan out").map(|p| p.to_string()),
+                config: sort_config(sub_matches)?,
+            };
+            dry_run = !sub_matches.is_present("update");
+        }
+        ("watch", Some(sub_matches)) => {
+            command = Command::Watch {
+                prune: sub_matches.is_present("prune"),
+                interval: duration_arg(sub_matches, "interval", "--interval")?,
+                config: sort_config(sub_matches)?,
+            };
+            dry_run = !sub_matches.is_present("update");
+        }
+        ("apply", Some(sub_matches)) => {
+            command = Command::Apply {
+                plan: sub_matches.value_of("plan").unwrap().to_string(),
+            };
+            dry_run = false;
+        }
+        ("resume", Some(_)) => {
+            command = Command::Resume;
+            dry_run = false;
+        }
+        ("snapshot", Some(sub_matches)) => {
+            command = Command::Snapshot {
+                file: sub_matches.value_of("file").unwrap().to_string(),
+            };
+        }
+        ("restore", Some(sub_matches)) => {
+            command = Command::Restore {
+                file: sub_matches.value_of("file").unwrap().to_string(),
+            };
+            dry_run = !sub_matches.is_present("update");
+        }
+        ("reconcile", Some(sub_matches)) => {
+            let mut config = Config::load(Path::new(sub_matches.value_of("config").unwrap()))?;
+            if let Some(archive_to) = sub_matches.value_of("archive to") {
+                config.archive_to = Some(archive_to.to_string());
+            }
+            command = Command::Reconcile { config: config };
             dry_run = !sub_matches.is_present("update");
         }
+        ("export", Some(sub_matches)) => {
+            command = Command::Export {
+                format: sub_matches.value_of("format").unwrap().parse()?,
+                out: sub_matches.value_of("out").map(|p| p.to_string()),
+            };
+        }
         _ => {}
     }
 
-    tokio::runtime::Builder::new_current_thread()
+    let quota_budget = match matches.value_of("quota budget") {
+        Some(budget) => Some(budget.parse::<u64>().map_err(|e| {
+            Error::BadInput(format!("invalid --quota-budget value {}: {}", budget, e))
+        })?),
+        None => None,
+    };
+
+    let max_retries = matches.value_of("max retries").unwrap();
+    let retry_policy = RetryPolicy {
+        max_retries: max_retries.parse().map_err(|e| {
+            Error::BadInput(format!(
+                "invalid --max-retries value {}: {}",
+                max_retries, e
+            ))
+        })?,
+        initial_delay: duration_arg(&matches, "retry delay", "--retry-delay")?,
+        max_delay: duration_arg(&matches, "max retry delay", "--max-retry-delay")?,
+    };
+
+    runtime()?.block_on(async_main(
+        required(&matches, "playlist_id", "<playlist_id>").to_owned(),
+        required(&matches, "client", "--client <client>").to_string(),
+        token_cache(matches.value_of("token cache"), profile)?,
+        auth_flow,
+        matches.value_of("timezone").unwrap().to_string(),
+        matches.value_of("output").unwrap().parse()?,
+        quota_budget,
+        retry_policy,
+        matches.value_of("journal").map(|j| j.to_string()),
+        dry_run,
+        matches.is_present("debug"),
+        matches.is_present("pause"),
+        command,
+    ))
+}
+
+/// default_journal returns the path of the journal file used for the playlist with the given id when none is
+/// specified. Each playlist has its own journal file so that an interrupted run of one playlist does not stop
+/// others from being changed.
+fn default_journal(playlist: &str) -> PathBuf {
+    let name: String = playlist
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    PathBuf::from(format!("playlist-manager-journal-{}.json", name))
+}
+
+/// runtime builds the runtime on which the subcommands run.
+fn runtime() -> Result<tokio::runtime::Runtime> {
+    Ok(tokio::runtime::Builder::new_current_thread()
         .enable_io()
         .enable_time()
-        .build()
-        .unwrap()
-        .block_on(async_main(
-            matches.value_of("playlist_id").unwrap().to_owned(),
-            matches.value_of("client").unwrap().to_string(),
-            matches.value_of("timezone").unwrap().to_string(),
-            dry_run,
-            matches.is_present("debug"),
-            matches.is_present("pause"),
-            sort,
-            prune,
-            max_playable,
-        ))
+        .build()?)
+}
+
+/// required returns the value of the given command line argument or, if it is missing, exits with a usage
+/// error. The argument is described by the given usage, e.g. "--client <client>".
+fn required<'a>(matches: &'a clap::ArgMatches, name: &str, usage: &str) -> &'a str {
+    matches.value_of(name).unwrap_or_else(|| missing(usage))
+}
+
+/// missing exits with a usage error, in the same way as clap does, for the missing argument described by the
+/// given usage.
+fn missing(usage: &str) -> ! {
+    clap::Error::with_description(
+        &format!(
+            "The following required arguments were not provided:\n    {}",
+            usage
+        ),
+        clap::ErrorKind::MissingRequiredArgument,
+    )
+    .exit()
+}
+
+/// duration_arg parses the value of the given command line argument, e.g. "10s", as a duration.
+fn duration_arg(matches: &clap::ArgMatches, name: &str, flag: &str) -> Result<Duration> {
+    let value = matches.value_of(name).unwrap();
+    humantime::parse_duration(value)
+        .map_err(|e| Error::BadInput(format!("invalid {} value {}: {}", flag, value, e)))
+}
+
+/// sort_args returns the command line arguments shared by the sort and watch subcommands.
+fn sort_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("prune")
+            .help("Removes extraneous entries from the playlist")
+            .long("prune")
+            .takes_value(false),
+        Arg::with_name("max playable")
+            .help("Maximum number of playable videos in the playlist. Others may be pruned. The default only applies without --config.")
+            .long("max-playable")
+            .takes_value(true)
+            .default_value("6"),
+        Arg::with_name("archive to")
+            .help("Adds pruned videos to the playlist with the given id before removing them")
+            .long("archive-to")
+            .takes_value(true),
+        Arg::with_name("config")
+            .help("Reads the pruning rules, maximum number of playable videos, and sort policy from the given config file")
+            .long("config")
+            .takes_value(true),
+        Arg::with_name("update")
+            .help("Update YouTube")
+            .takes_value(false)
+            .long("update"),
+        Arg::with_name("category order")
+            .help("Comma-separated order of the categories viewable, scheduled, invalid, and blocked")
+            .long("category-order")
+            .takes_value(true),
+        Arg::with_name("direction")
+            .help("Order of the videos in each category: descending (newest first) or ascending")
+            .long("direction")
+            .takes_value(true)
+            .possible_values(&["ascending", "descending"]),
+        Arg::with_name("tie breakers")
+            .help("Comma-separated tie-breakers for videos with the same time: title or date-added")
+            .long("tie-breakers")
+            .takes_value(true),
+    ]
+}
+
+/// sort_config returns the config given by the command line flags of the sort or watch subcommand.
+fn sort_config(sub_matches: &clap::ArgMatches) -> Result<Config> {
+    let mut config = Config::default();
+    if let Some(path) = sub_matches.value_of("config") {
+        config = Config::load(Path::new(path))?;
+    }
+    // --max-playable overrides the config file. Its default only applies if there is no config file: a config
+    // file without max_playable keeps any number of playable videos.
+    if sub_matches.occurrences_of("max playable") > 0 || sub_matches.value_of("config").is_none() {
+        let max = sub_matches.value_of("max playable").unwrap();
+        config.max_playable = Some(max.parse::<usize>().map_err(|e| {
+            Error::BadInput(format!("invalid --max-playable value {}: {}", max, e))
+        })?);
+    }
+    config.sort = sort_policy(sub_matches, config.sort)?;
+    if let Some(archive_to) = sub_matches.value_of("archive to") {
+        config.archive_to = Some(archive_to.to_string());
+    }
+    Ok(config)
+}
+
+/// sort_policy overrides the given sort policy with the command line flags of the sort or watch subcommand.
+fn sort_policy(sub_matches: &clap::ArgMatches, mut policy: SortPolicy) -> Result<SortPolicy> {
+    if let Some(order) = sub_matches.value_of("category order") {
+        policy.categories = parse_list(order, "--category-order")?;
+    }
+    if let Some(direction) = sub_matches.value_of("direction") {
+        policy.direction = direction.parse().map_err(Error::BadInput)?;
+    }
+    if let Some(tie_breakers) = sub_matches.value_of("tie breakers") {
+        policy.tie_breakers = parse_list(tie_breakers, "--tie-breakers")?;
+    }
+    policy
+        .validate()
+        .map_err(|e| Error::BadInput(format!("invalid --category-order value: {}", e)))?;
+    Ok(policy)
+}
+
+fn parse_list<T: std::str::FromStr<Err = String>>(list: &str, flag: &str) -> Result<Vec<T>> {
+    list.split(',')
+        .map(|s| s.trim().parse())
+        .collect::<std::result::Result<Vec<T>, String>>()
+        .map_err(|e| Error::BadInput(format!("invalid {} value {}: {}", flag, list, e)))
+}
+
+/// Command is the operation to perform on the playlist.
+enum Command {
+    /// Print prints the playlist.
+    Print,
+    /// Sort sorts, and optionally prunes, the playlist according to the given config. If plan_out is present,
+    /// the changes are saved to that file instead of being made.
+    Sort {
+        prune: bool,
+        plan_out: Option<String>,
+        config: Config,
+    },
+    /// Watch sorts, and optionally prunes, the playlist according to the given config whenever it changes,
+    /// checking at the given interval until the process is terminated.
+    Watch {
+        prune: bool,
+        interval: Duration,
+        config: Config,
+    },
+    /// Apply makes the changes in the given plan file.
+    Apply { plan: String },
+    /// Resume finishes applying the changes recorded in the journal of an interrupted run.
+    Resume,
+    /// Snapshot saves the contents of the playlist to the given file.
+    Snapshot { file: String },
+    /// Restore restores the playlist to the contents saved in the given snapshot file.
+    Restore { file: String },
+    /// Reconcile changes the playlist to match the desired state described by the given config.
+    Reconcile { config: Config },
+    /// Export prints the playlist in the given format or, if out is present, writes it to the given file.
+    Export {
+        format: ExportFormat,
+        out: Option<String>,
+    },
 }
 
 async fn async_main(
     playlist: String,
     client_id_path: String,
+    token_cache: TokenCacheFile,
+    auth_flow: AuthFlow,
     timezone: String,
+    output: OutputFormat,
+    quota_budget: Option<u64>,
+    retry_policy: RetryPolicy,
+    journal: Option<String>,
     dry_run: bool,
     debug: bool,
     pause: bool,
-    sort: bool,
-    prune: bool,
-    max_catch_up: usize,
+    command: Command,
 ) -> Result<()> {
-    let client_id = read_application_secret(client_id_path).await.unwrap();
-
-    // Create an authenticator that uses an InstalledFlow to authenticate. The
-    // authentication tokens are persisted to a file. The
-    // authenticator takes care of caching tokens to disk and refreshing tokens once
-    // they've expired. Use a custom flow delegate to launch a browser automatically.
-    debug!("building installed flow authenticator");
-    let auth =
-        InstalledFlowAuthenticator::builder(client_id, InstalledFlowReturnMethod::HTTPRedirect)
-            .flow_delegate(Box::new(CustomInstalledFlowDelegate))
-            .persist_tokens_to_disk("playlist-manager-tokencache.json")
-            .build()
-            .await
-            .unwrap();
-    debug!("installed flow authenticator built successfully");
+    let client_id = read_client_id(&client_id_path).await?;
 
-    let hub = YouTube::new(
-        hyper::Client::builder().build(hyper_rustls::HttpsConnector::with_native_roots()),
-        auth,
+    // The hub and the backend, which lists playlist items itself, each need an authenticator. They share a
+    // token cache so that the user only has to authenticate once.
+    let tokens = load_tokens(&token_cache)?;
+    let client = hyper::Client::builder().build(hyper_rustls::HttpsConnector::with_native_roots());
+    let hub_auth = authenticator(client_id.clone(), tokens.clone(), auth_flow).await?;
+
+    // Ask only for the access the command needs, and ask for it up front, so that the user is asked at most
+    // once and before anything is done. A cached token for modifying the playlist is also good for reading it,
+    // so the user is not asked again for read-only access.
+    let cached = tokens.scopes();
+    let is_cached = |scope: &Scope| cached.iter().any(|s| s == scope.as_ref());
+    let (scope, purpose) = if !dry_run {
+        (Scope::Full, "read and modify the playlist")
+    } else if is_cached(&Scope::Full) && !is_cached(&Scope::Readonly) {
+        (Scope::Full, "read the playlist")
+    } else {
+        (Scope::Readonly, "read the playlist")
+    };
+    let status = if is_cached(&scope) {
+        "Using the cached authorisation"
+    } else {
+        "Authorisation is needed"
+    };
+    eprintln!(
+        "{} to {} (cached scopes: {}).",
+        status,
+        purpose,
+        scope_names(&cached)
     );
+    hub_auth
+        .token(&[scope.as_ref()])
+        .await
+        .map_err(|e| Error::Auth(e.to_string()))?;
+    let hub = YouTube::new(client.clone(), hub_auth);
 
-    let play_list = youtube_manager::playlist::new(hub, &playlist, timezone, dry_run, debug);
+    let quota = Quota::new(quota_budget);
+    let play_list = youtube_manager::playlist::new(
+        // Retries are metered, since each attempt uses quota.
+        retry::retry(
+            quota::meter(
+                youtube_manager::youtube::new(
+                    hub,
+                    client,
+                    authenticator(client_id, tokens, auth_flow).await?,
+                    scope,
+                ),
+                quota.clone(),
+            ),
+            retry_policy,
+        ),
+        &playlist,
+        timezone,
+        dry_run,
+        debug,
+        output,
+        Some(journal.map_or_else(|| default_journal(&playlist), PathBuf::from)),
+    )?;
 
-    if sort {
-        eprintln!("Input playlist:");
-    }
-    play_list.print().await?;
+    // In a structured output format, only the items or the changes are printed on standard output.
+    let text = output == OutputFormat::Text;
+    match command {
+        Command::Print => play_list.print().await?,
+
+        Command::Sort {
+            prune,
+            plan_out,
+            config,
+        } => {
+            if text {
+                eprintln!("Input playlist:");
+                play_list.print().await?;
+            }
+
+            if let Some(path) = plan_out {
+                eprintln!("\nPlanning...");
+                let plan = play_list.plan(&config, prune).await?;
+                plan.save(Path::new(&path))?;
+                eprintln!(
+                    "{}\n\nThe plan has been saved to {}. To make these changes, run the apply command with this file.",
+                    plan, path
+                );
+            } else {
+                if prune {
+                    eprintln!("\nSorting and pruning...");
+                    play_list.prune(&config).await?;
+                } else {
+                    eprintln!("\nSorting...");
+                    play_list.sort(&config.sort).await?;
+                }
 
-    if sort {
-        if prune {
-            eprintln!("\nSorting and pruning...");
-            play_list.prune(max_catch_up).await?;
-        } else {
-            eprintln!("\nSorting...");
-            play_list.sort().await?;
+                if !dry_run {
+                    eprintln!("Done.");
+                    if text {
+                        eprintln!("\nOutput playlist:");
+                        play_list.print().await?;
+                    }
+                } else {
+                    eprintln!(
+                        "\nThis was only a dry run. To make changes to the YouTube playlist, repeat the command and add --update."
+                    );
+                }
+            }
         }
 
-        if !dry_run {
+        Command::Watch {
+            prune,
+            interval,
+            config,
+        } => {
+            eprintln!(
+                "Watching playlist {} every {}...",
+                playlist,
+                humantime::format_duration(interval)
+            );
+            let mut watcher = Watcher::new(config, prune, dry_run);
+            let mut ticks = tokio::time::interval(interval);
+            ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            let shutdown = shutdown();
+            tokio::pin!(shutdown);
+            loop {
+                // A cycle in progress is allowed to finish so that the playlist is not left half updated.
+                tokio::select! {
+                    _ = ticks.tick() => {}
+                    result = &mut shutdown => {
+                        result?;
+                        break;
+                    }
+                }
+                // The budget applies to each cycle, so that a watcher which has used up its budget carries on
+                // with the next cycle.
+                quota.start_period();
+                match watcher.cycle(&play_list).await {
+                    Ok(cycle) => eprintln!(
+                        "{}: {} ({} quota unit(s) used so far)",
+                        timestamp(),
+                        cycle,
+                        quota.used().units()
+                    ),
+                    // Authorisation, local I/O, an interrupted run, and invalid input need attention, so
+                    // further cycles cannot help.
+                    Err(e)
+                        if matches!(
+                            e,
+                            Error::Auth(_)
+                                | Error::Io(_)
+                                | Error::Interrupted(_)
+                                | Error::BadInput(_)
+                        ) =>
+                    {
+                        return Err(e)
+                    }
+                    // Other failures, such as the playlist changing during the cycle, an API outage which
+                    // outlasted the retries, or running out of quota, may clear up, so try again next time.
+                    Err(e) => eprintln!("{}: {}", timestamp(), e),
+                }
+            }
+            eprintln!("{}: stopped watching", timestamp());
+        }
+
+        Command::Resume => {
+            play_list.resume().await?;
             eprintln!("Done.");
-            eprintln!("\nOutput playlist:");
-            play_list.print().await?;
-        } else {
+            if text {
+                eprintln!("\nOutput playlist:");
+                play_list.print().await?;
+            }
+        }
+
+        Command::Apply { plan } => {
+            let plan = Plan::load(Path::new(&plan))?;
+            eprintln!("Applying {}", plan);
+            play_list.apply(&plan).await?;
+            eprintln!("Done.");
+            if text {
+                eprintln!("\nOutput playlist:");
+                play_list.print().await?;
+            }
+        }
+
+        Command::Snapshot { file } => {
+            let snapshot = Snapshot::new(&playlist, play_list.items().await?);
+            snapshot.save(Path::new(&file))?;
             eprintln!(
-                "\nThis was only a dry run. To make changes to the YouTube playlist, repeat the command and add --update."
+                "A snapshot of {} playlist items has been saved to {}.",
+                snapshot.items.len(),
+                file
             );
         }
+
+        Command::Restore { file } => {
+            let snapshot = Snapshot::load(Path::new(&file))?;
+            if text {
+                eprintln!("Input playlist:");
+                play_list.print().await?;
+            }
+
+            eprintln!("\nRestoring snapshot taken at {}...", snapshot.taken_at);
+            play_list.restore(&snapshot).await?;
+
+            if !dry_run {
+                eprintln!("Done.");
+                if text {
+                    eprintln!("\nOutput playlist:");
+                    play_list.print().await?;
+                }
+            } else {
+                eprintln!(
+                    "\nThis was only a dry run. To make changes to the YouTube playlist, repeat the command and add --update."
+                );
+            }
+        }
+
+        Command::Reconcile { config } => {
+            if text {
+                eprintln!("Input playlist:");
+                play_list.print().await?;
+            }
+
+            eprintln!("\nReconciling...");
+            play_list.reconcile(&config).await?;
+
+            if !dry_run {
+                eprintln!("Done.");
+                if text {
+                    eprintln!("\nOutput playlist:");
+                    play_list.print().await?;
+                }
+            } else {
+                eprintln!(
+                    "\nThis was only a dry run. To make changes to the YouTube playlist, repeat the command and add --update."
+                );
+            }
+        }
+
+        Command::Export { format, out } => {
+            let exported = export::export(&playlist, &play_list.items().await?, format)?;
+            match out {
+                Some(path) => fs::write(&path, exported)?,
+                None => print!("{}", exported),
+            }
+        }
     }
 
+    eprintln!("YouTube API quota used: {}", quota.used());
+
     if pause {
         println!("\nCommand complete, press return to exit...");
         let mut user_input = String::new();
@@ -191,25 +835,299 @@ async fn async_main(
     Ok(())
 }
 
+/// TokenCacheFile is a file in which tokens are cached.
+struct TokenCacheFile {
+    path: PathBuf,
+    /// import_legacy is true if tokens cached in the current directory by earlier versions of the application,
+    /// which only supported one account, may be moved into the file.
+    import_legacy: bool,
+}
+
+/// token_cache returns the given token cache file or, if there is none, the token cache of the given profile.
+/// The profiles directory is only looked for in the second case, so that it need not exist if a file is given.
+/// Legacy tokens are only imported into a given file or the default profile, so that they do not end up in the
+/// profile of another account.
+fn token_cache(path: Option<&str>, profile: &str) -> Result<TokenCacheFile> {
+    match path {
+        Some(path) => Ok(TokenCacheFile {
+            path: PathBuf::from(path),
+            import_legacy: true,
+        }),
+        None => Ok(TokenCacheFile {
+            path: Profiles::new(&Profiles::default_dir()?).token_cache(profile)?,
+            import_legacy: profile == DEFAULT_PROFILE,
+        }),
+    }
+}
+
+/// load_tokens loads the given token cache, which is encrypted if an encryption key or passphrase is set, and
+/// moves into it any tokens cached in the current directory by earlier versions of the application, if it may.
+fn load_tokens(token_cache: &TokenCacheFile) -> Result<TokenCache> {
+    let tokens = TokenCache::load(&token_cache.path, Encryption::from_env()?)?;
+    let legacy = Path::new(LEGACY_TOKEN_CACHE);
+    if token_cache.import_legacy {
+        tokens.import(legacy)?;
+    } else if legacy.exists() {
+        eprintln!(
+            "Warning: the tokens in {} were cached by an earlier version of the application. They are only \
+             moved into the {} profile, or a file given by --token-cache, so run the application once with that.",
+            LEGACY_TOKEN_CACHE, DEFAULT_PROFILE
+        );
+    }
+    Ok(tokens)
+}
+
+/// auth lists the stored profiles or, for the given token cache file or, if there is none, the given profile,
+/// authorises access to YouTube and caches the tokens, checks the cached tokens, or deletes the cached tokens.
+async fn auth(
+    sub_matches: &clap::ArgMatches<'_>,
+    token_cache_path: Option<&str>,
+    profile: &str,
+    client_id_path: Option<&str>,
+    auth_flow: AuthFlow,
+) -> Result<()> {
+    let cached_in = match token_cache_path {
+        Some(path) => format!("file {}", path),
+        None => format!("profile {}", profile),
+    };
+    match sub_matches.subcommand_name() {
+        Some("login") => {
+            let client_id =
+                read_client_id(client_id_path.unwrap_or_else(|| missing("--client <client>")))
+                    .await?;
+            let tokens = load_tokens(&token_cache(token_cache_path, profile)?)?;
+            let auth = authenticator(client_id, tokens.clone(), auth_flow).await?;
+            // A token for modifying playlists is also used for reading them, so this one authorisation covers
+            // all the subcommands.
+            auth.token(&[Scope::Full.as_ref()])
+                .await
+                .map_err(|e| Error::Auth(e.to_string()))?;
+            eprintln!(
+                "Logged in. Tokens for scopes {} are cached in {}.",
+                scope_names(&tokens.scopes()),
+                cached_in
+            );
+        }
+        Some("status") => {
+            let client_id =
+                read_client_id(client_id_path.unwrap_or_else(|| missing("--client <client>")))
+                    .await?;
+            status(
+                &cached_in,
+                client_id,
+                load_tokens(&token_cache(token_cache_path, profile)?)?,
+            )
+            .await?;
+        }
+        Some("logout") => {
+            match token_cache_path {
+                Some(path) => match fs::remove_file(path) {
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                        return Err(Error::BadInput(format!("there is no token cache {}", path)))
+                    }
+                    result => result?,
+                },
+                None => Profiles::new(&Profiles::default_dir()?).remove(profile)?,
+            }
+            eprintln!(
+                "Logged out. The tokens cached in {} have been deleted.",
+                cached_in
+            );
+        }
+        _ => {
+            for name in Profiles::new(&Profiles::default_dir()?).list()? {
+                println!("{}", name);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// status prints where the given tokens are cached, which is described by cached_in, the account and the
+/// scopes for which there are cached tokens, together with the expiry of each access token. Expired access
+/// tokens are refreshed. An error is returned, rather than asking the user to authorise access, if there are no
+/// cached tokens or they cannot be refreshed.
+async fn status(cached_in: &str, client_id: ApplicationSecret, tokens: TokenCache) -> Result<()> {
+    let cached = tokens.scopes();
+    let auth = authenticator(client_id, tokens, AuthFlow::NonInteractive).await?;
+    println!("Tokens cached in {}", cached_in);
+
+    let mut valid = None;
+    for scope in vec![Scope::Readonly, Scope::Full] {
+        if !cached.iter().any(|s| s == scope.as_ref()) {
+            continue;
+        }
+        let name = scope_names(&[scope.as_ref().to_string()]);
+        let token = auth.token(&[scope.as_ref()]).await.map_err(|e| {
+            Error::Auth(format!(
+                "the cached token for scope {} is no longer valid, so run auth login: {}",
+                name, e
+            ))
+        })?;
+        let expiry = token.expiration_time().map_or("never".to_string(), |t| {
+            t.to_rfc3339_opts(SecondsFormat::Secs, true)
+        });
+        println!("Scope {}: access token expires {}", name, expiry);
+        valid = Some(scope);
+    }
+    let scope = valid.ok_or_else(|| {
+        Error::Auth(format!(
+            "there are no tokens cached in {}, so run auth login",
+            cached_in
+        ))
+    })?;
+
+    let client = hyper::Client::builder().build(hyper_rustls::HttpsConnector::with_native_roots());
+    let (_, channels) = YouTube::new(client, auth)
+        .channels()
+        .list(&vec!["snippet".into()])
+        .mine(true)
+        .add_scope(scope.as_ref())
+        .doit()
+        .await?;
+    match channels.items.unwrap_or_default().into_iter().next() {
+        Some(channel) => println!(
+            "Account: {} (channel {})",
+            channel
+                .snippet
+                .and_then(|s| s.title)
+                .unwrap_or_else(|| "untitled".to_string()),
+            channel.id.unwrap_or_default()
+        ),
+        None => println!("Account: no YouTube channel"),
+    }
+    Ok(())
+}
+
+/// scope_names returns the given scopes, without the common URL prefix, for presenting to the user.
+fn scope_names(scopes: &[String]) -> String {
+    if scopes.is_empty() {
+        return "none".to_string();
+    }
+    scopes
+        .iter()
+        .map(|s| s.trim_start_matches("https://www.googleapis.com/auth/"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// read_client_id reads the client id file with the given path.
+async fn read_client_id(path: &str) -> Result<ApplicationSecret> {
+    read_application_secret(path)
+        .await
+        .map_err(|e| Error::BadInput(format!("couldn't read client id file {}: {}", path, e)))
+}
+
+/// AuthFlow is the way the user authorises access to YouTube when there is no cached token.
 #[derive(Copy, Clone)]
-pub struct CustomInstalledFlowDelegate;
+enum AuthFlow {
+    /// Installed has the user sign in with a browser, which is launched if browser is true.
+    Installed { browser: bool },
+    /// Device has the user enter a code on another device, e.g. a phone, so that no browser is needed.
+    Device,
+    /// NonInteractive fails instead of asking the user, so that only cached tokens, refreshed if necessary, are
+    /// used.
+    NonInteractive,
+}
+
+/// authenticator creates an authenticator that uses the given flow to authenticate. The authentication
+/// tokens are stored in the given cache. The authenticator takes care of refreshing tokens once they've
+/// expired. Custom flow delegates launch a browser automatically, unless asked not to, or present the code for
+/// the device flow.
+async fn authenticator(
+    client_id: ApplicationSecret,
+    tokens: TokenCache,
+    flow: AuthFlow,
+) -> Result<Authenticator<HttpsConnector<HttpConnector>>> {
+    let auth = match flow {
+        AuthFlow::Installed { browser } => {
+            debug!("building installed flow authenticator");
+            InstalledFlowAuthenticator::builder(client_id, InstalledFlowReturnMethod::HTTPRedirect)
+                .flow_delegate(Box::new(CustomInstalledFlowDelegate { browser: browser }))
+                .with_storage(Box::new(tokens))
+                .build()
+                .await
+        }
+        AuthFlow::Device => {
+            debug!("building device flow authenticator");
+            DeviceFlowAuthenticator::builder(client_id)
+                .flow_delegate(Box::new(CustomDeviceFlowDelegate))
+                .with_storage(Box::new(tokens))
+                .build()
+                .await
+        }
+        AuthFlow::NonInteractive => {
+            debug!("building non-interactive authenticator");
+            InstalledFlowAuthenticator::builder(client_id, InstalledFlowReturnMethod::Interactive)
+                .flow_delegate(Box::new(NonInteractiveFlowDelegate))
+                .with_storage(Box::new(tokens))
+                .build()
+                .await
+        }
+    }
+    .map_err(|e| Error::Auth(format!("couldn't build authenticator: {}", e)))?;
+    debug!("authenticator built successfully");
+    Ok(auth)
+}
+
+/// shutdown completes when the process is asked to terminate, by SIGTERM or Ctrl-C.
+#[cfg(unix)]
+async fn shutdown() -> Result<()> {
+    let mut sigterm = signal::unix::signal(signal::unix::SignalKind::terminate())?;
+    tokio::select! {
+        _ = sigterm.recv() => Ok(()),
+        result = signal::ctrl_c() => Ok(result?),
+    }
+}
+
+/// shutdown completes when the process is asked to terminate by Ctrl-C.
+#[cfg(not(unix))]
+async fn shutdown() -> Result<()> {
+    Ok(signal::ctrl_c().await?)
+}
+
+/// timestamp returns the current time for logging.
+fn timestamp() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// CustomInstalledFlowDelegate presents the link for signing in and, if browser is true, opens it in a browser.
+#[derive(Copy, Clone)]
+pub struct CustomInstalledFlowDelegate {
+    browser: bool,
+}
+
 impl InstalledFlowDelegate for CustomInstalledFlowDelegate {
     fn present_user_url<'a>(
         &'a self,
         url: &'a str,
         need_code: bool,
     ) -> Pin<Box<dyn Future<Output = core::result::Result<String, String>> + Send + 'a>> {
-        Box::pin(present_user_url(url, need_code))
+        Box::pin(present_user_url(url, need_code, self.browser))
     }
 }
 
-async fn present_user_url(url: &str, need_code: bool) -> core::result::Result<String, String> {
-    if need_code {
+async fn present_user_url(
+    url: &str,
+    need_code: bool,
+    browser: bool,
+) -> core::result::Result<String, String> {
+    if browser {
         println!(
-            "Launching a browser.\n\n(If a browser does not appear, please copy this link into a browser's address bar and press return: {})\n\nFollow the instructions displayed in the browser and enter the code displayed here: ",
+            "Launching a browser.\n\n(If a browser does not appear, please copy this link into a browser's address bar and press return: {})\n",
             url
         );
         launch_browser(url).await?;
+    } else {
+        println!(
+            "Please copy this link into a browser's address bar and press return: {}\n",
+            url
+        );
+    }
+    if need_code {
+        println!(
+            "Follow the instructions displayed in the browser and enter the code displayed here: "
+        );
         let mut user_input = String::new();
         tokio::io::BufReader::new(tokio::io::stdin())
             .read_line(&mut user_input)
@@ -219,11 +1137,8 @@ async fn present_user_url(url: &str, need_code: bool) -> core::result::Result<St
         user_input.truncate(user_input.trim_end().len());
         Ok(user_input)
     } else {
-        println!(
-            "Launching a browser.\n\n(If a browser does not appear, please copy this link into a browser's address bar and press return: {})\n\nFollow the instructions displayed in the browser.",
-            url
-        );
-        launch_browser(url).await
+        println!("Follow the instructions displayed in the browser.");
+        Ok(String::new())
     }
 }
 
@@ -235,3 +1150,37 @@ async fn launch_browser(url: &str) -> core::result::Result<String, String> {
         Ok(String::new())
     }
 }
+
+/// CustomDeviceFlowDelegate presents the verification URL and code for the device flow. The authenticator then
+/// polls until the code has been approved, or has expired.
+#[derive(Copy, Clone)]
+pub struct CustomDeviceFlowDelegate;
+
+impl DeviceFlowDelegate for CustomDeviceFlowDelegate {
+    fn present_user_code<'a>(
+        &'a self,
+        device_auth_resp: &'a DeviceAuthResponse,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'a>> {
+        Box::pin(async move {
+            println!(
+                "On any device with a browser, go to {} and enter the code {}\n\nWaiting for the code to be approved...",
+                device_auth_resp.verification_uri, device_auth_resp.user_code
+            );
+        })
+    }
+}
+
+/// NonInteractiveFlowDelegate refuses to ask the user to authorise access, so that an authenticator which
+/// needs a new token fails instead.
+#[derive(Copy, Clone)]
+pub struct NonInteractiveFlowDelegate;
+
+impl InstalledFlowDelegate for NonInteractiveFlowDelegate {
+    fn present_user_url<'a>(
+        &'a self,
+        _url: &'a str,
+        _need_code: bool,
+    ) -> Pin<Box<dyn Future<Output = core::result::Result<String, String>> + Send + 'a>> {
+        Box::pin(async { Err("authorisation is needed".to_string()) })
+    }
+}
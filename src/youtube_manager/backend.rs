@@ -0,0 +1,57 @@
+use super::error::Result;
+use super::quota::Usage;
+use async_trait::async_trait;
+use google_youtube3::api::{PlaylistItemListResponse, Video};
+
+/// MAX_VIDEO_IDS is the maximum number of video ids which may be looked up in a single call. It is also the
+/// maximum number of playlist items in a page.
+pub const MAX_VIDEO_IDS: usize = 50;
+
+/// PlaylistBackend provides the YouTube Data API operations needed to manage a playlist. The YouTube hub
+/// is one implementation (see the youtube module) and an in-memory fake is another (see the memory module).
+#[async_trait]
+pub trait PlaylistBackend: Send + Sync {
+    /// list_items returns a page of up to MAX_VIDEO_IDS of the items in the playlist with the given id, so that
+    /// the videos of a page can be looked up in one call. The first page is returned if page_token is None. If
+    /// etag is present, it is the ETag of a previous response for the same page and None is returned if the
+    /// page has not changed since then.
+    async fn list_items(
+        self: &Self,
+        playlist_id: &str,
+        page_token: &Option<String>,
+        etag: Option<&str>,
+    ) -> Result<Option<PlaylistItemListResponse>>;
+
+    /// videos looks up the live streaming and content details of the videos with the given ids, of which there
+    /// may be at most MAX_VIDEO_IDS. Videos which do not exist, e.g. because they have been deleted, are omitted
+    /// from the result.
+    async fn videos(self: &Self, video_ids: &[String]) -> Result<Vec<Video>>;
+
+    /// update_position moves the given playlist item, which refers to the given video, to the given position
+    /// in the playlist. Items at or after that position move down the playlist to make room.
+    async fn update_position(
+        self: &Self,
+        playlist_id: &str,
+        playlist_item_id: &str,
+        video_id: &str,
+        position: u32,
+    ) -> Result<()>;
+
+    /// insert adds the given video to the given playlist and returns the id of the new playlist item. The item
+    /// is inserted at the given position or, if position is None, at the end of the playlist.
+    async fn insert(
+        self: &Self,
+        playlist_id: &str,
+        video_id: &str,
+        position: Option<u32>,
+    ) -> Result<String>;
+
+    /// delete removes the given playlist item from its playlist.
+    async fn delete(self: &Self, playlist_item_id: &str) -> Result<()>;
+
+    /// check_quota returns an error if making the given calls would exceed the budget for quota use, if any.
+    /// There is no budget by default.
+    fn check_quota(self: &Self, _planned: &Usage) -> Result<()> {
+        Ok(())
+    }
+}
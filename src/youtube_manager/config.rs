@@ -0,0 +1,131 @@
+use super::error::{Error, Result};
+use super::rules::RuleSet;
+use super::sort::SortPolicy;
+use serde::Deserialize;
+use std::{fs, path::Path};
+
+/// Config describes the desired state of a playlist. It is read from a TOML file such as:
+///
+/// ```toml
+/// # videos which are always present, at the top of the playlist, in this order
+/// pinned = ["dQw4w9WgXcQ"]
+/// # videos which are never present
+/// excluded = ["oHg5SJYRHA0"]
+/// # the maximum number of playable videos, not counting pinned videos
+/// max_playable = 6
+/// # a playlist to which pruned videos are added before they are removed
+/// archive_to = "PLxxxxxxxxxxxxxxxx"
+///
+/// # rules for pruning other videos, which replace the default rules "blocked" and "invalid"
+/// [[rules]]
+/// rule = "blocked"
+/// [[rules]]
+/// rule = "older-than"
+/// days = 30
+///
+/// # how to sort the playlist (see SortPolicy)
+/// [sort]
+/// categories = ["viewable", "scheduled", "invalid", "blocked"]
+/// direction = "descending"
+/// tie_breakers = ["date-added", "title"]
+/// ```
+#[derive(Deserialize, Default, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// pinned lists the ids of videos which must be present in the playlist. Pinned videos are placed at the
+    /// top of the playlist, in the order given, and are never pruned.
+    #[serde(default)]
+    pub pinned: Vec<String>,
+    /// excluded lists the ids of videos which must not be present in the playlist.
+    #[serde(default)]
+    pub excluded: Vec<String>,
+    /// max_playable is the maximum number of playable videos, not counting pinned videos, to keep in the
+    /// playlist. If it is absent, surplus videos are not removed.
+    pub max_playable: Option<usize>,
+    /// rules are the rules for pruning videos other than pinned and excluded videos. If they are absent, the
+    /// default rules are used.
+    #[serde(default)]
+    pub rules: RuleSet,
+    /// archive_to is the id of a playlist to which pruned and excluded videos are added before they are
+    /// removed. If it is absent, the videos are simply removed.
+    pub archive_to: Option<String>,
+    /// sort is the policy for sorting the playlist. If it is absent, the default policy is used.
+    #[serde(default)]
+    pub sort: SortPolicy,
+}
+
+impl Config {
+    /// load reads a config from the given TOML file.
+    pub fn load(path: &Path) -> Result<Config> {
+        Config::parse(&fs::read_to_string(path)?)
+            .map_err(|e| Error::BadInput(format!("invalid config file {}: {}", path.display(), e)))
+    }
+
+    /// parse parses a config in TOML format.
+    pub fn parse(s: &str) -> std::result::Result<Config, String> {
+        let config: Config = toml::from_str(s).map_err(|e| e.to_string())?;
+        if let Some(id) = config.pinned.iter().find(|id| config.excluded.contains(id)) {
+            return Err(format!("video {} is both pinned and excluded", id));
+        }
+        config.sort.validate()?;
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::rules::Rule;
+    use super::super::sort::{Category::*, Direction, TieBreaker};
+    use super::*;
+
+    #[test]
+    fn parse_config() {
+        assert_eq!(
+            Config::parse(
+                r#"
+                pinned = ["v1", "v2"]
+                excluded = ["v3"]
+                max_playable = 4
+                archive_to = "pl2"
+
+                [[rules]]
+                rule = "older-than"
+                days = 30
+
+                [sort]
+                categories = ["scheduled", "viewable", "blocked", "invalid"]
+                tie_breakers = ["title"]
+                "#
+            ),
+            Ok(Config {
+                pinned: vec!["v1".to_string(), "v2".to_string()],
+                excluded: vec!["v3".to_string()],
+                max_playable: Some(4),
+                archive_to: Some("pl2".to_string()),
+                rules: RuleSet {
+                    rules: vec![Rule::OlderThan { days: 30 }],
+                },
+                sort: SortPolicy {
+                    categories: vec![Scheduled, Viewable, Blocked, Invalid],
+                    direction: Direction::Descending,
+                    tie_breakers: vec![TieBreaker::Title],
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn parse_empty_config() {
+        assert_eq!(Config::parse(""), Ok(Default::default()));
+    }
+
+    #[test]
+    fn parse_rejects_invalid_config() {
+        assert!(Config::parse("pinned = \"v1\"").is_err());
+        assert!(Config::parse("unknown = 1").is_err());
+        assert!(Config::parse("pinned = [\"v1\"]\nexcluded = [\"v1\"]").is_err());
+        assert!(Config::parse("[sort]\ncategories = [\"viewable\"]").is_err());
+        assert!(Config::parse("[sort]\ndirection = \"sideways\"").is_err());
+        assert!(Config::parse("[[rules]]\nrule = \"unknown\"").is_err());
+    }
+}
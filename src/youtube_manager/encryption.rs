@@ -0,0 +1,295 @@
+use super::error::{Error, Result};
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN},
+    pbkdf2,
+    rand::{SecureRandom, SystemRandom},
+};
+use serde::{Deserialize, Serialize};
+use std::{env, num::NonZeroU32};
+
+/// KEY_VARIABLE is the environment variable which holds a base64-encoded 256-bit encryption key.
+pub const KEY_VARIABLE: &str = "PLAYLIST_MANAGER_TOKEN_KEY";
+
+/// PASSPHRASE_VARIABLE is the environment variable which holds a passphrase from which the encryption key is
+/// derived.
+pub const PASSPHRASE_VARIABLE: &str = "PLAYLIST_MANAGER_TOKEN_PASSPHRASE";
+
+/// ITERATIONS is the number of PBKDF2 iterations used to derive a key from a passphrase.
+const ITERATIONS: u32 = 600_000;
+
+const KEY_LEN: usize = 32;
+const SALT_LEN: usize = 16;
+
+/// Encryption encrypts and decrypts data, such as cached tokens, with ChaCha20-Poly1305. The key is either
+/// given or derived from a passphrase with PBKDF2-HMAC-SHA256 and a random salt, which is stored with the
+/// encrypted data.
+#[derive(Clone)]
+pub struct Encryption {
+    secret: Secret,
+    iterations: u32,
+}
+
+#[derive(Clone)]
+enum Secret {
+    Key([u8; KEY_LEN]),
+    Passphrase(String),
+}
+
+/// Envelope is the JSON format of encrypted data. Binary values are base64-encoded.
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    encryption: String,
+    /// kdf describes how the key was derived from a passphrase, if it was.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    kdf: Option<Kdf>,
+    nonce: String,
+    ciphertext: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Kdf {
+    algorithm: String,
+    iterations: u32,
+    salt: String,
+}
+
+const ENCRYPTION: &str = "chacha20-poly1305";
+const KDF: &str = "pbkdf2-hmac-sha256";
+
+impl Encryption {
+    /// with_key constructs an Encryption which uses the given base64-encoded 256-bit key.
+    pub fn with_key(key: &str) -> Result<Encryption> {
+        let bytes = base64::decode(key.trim())
+            .map_err(|e| Error::BadInput(format!("invalid encryption key: {}", e)))?;
+        if bytes.len() != KEY_LEN {
+            return Err(Error::BadInput(format!(
+                "invalid encryption key: it must be {} bytes long, not {}",
+                KEY_LEN,
+                bytes.len()
+            )));
+        }
+        let mut key = [0; KEY_LEN];
+        key.copy_from_slice(&bytes);
+        Ok(Encryption {
+            secret: Secret::Key(key),
+            iterations: ITERATIONS,
+        })
+    }
+
+    /// with_passphrase constructs an Encryption which derives its key from the given passphrase.
+    pub fn with_passphrase(passphrase: &str) -> Result<Encryption> {
+        if passphrase.is_empty() {
+            return Err(Error::BadInput(
+                "the encryption passphrase must not be empty".to_string(),
+            ));
+        }
+        Ok(Encryption {
+            secret: Secret::Passphrase(passphrase.to_string()),
+            iterations: ITERATIONS,
+        })
+    }
+
+    /// from_env constructs an Encryption from the key in the PLAYLIST_MANAGER_TOKEN_KEY environment variable or,
+    /// if that is not set, the passphrase in the PLAYLIST_MANAGER_TOKEN_PASSPHRASE environment variable.
+    /// None is returned if neither is set.
+    pub fn from_env() -> Result<Option<Encryption>> {
+        if let Ok(key) = env::var(KEY_VARIABLE) {
+            return Ok(Some(Encryption::with_key(&key)?));
+        }
+        match env::var(PASSPHRASE_VARIABLE) {
+            Ok(passphrase) => Ok(Some(Encryption::with_passphrase(&passphrase)?)),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// encrypt returns the given data encrypted, in JSON format. A new nonce, and salt if the key is derived
+    /// from a passphrase, are used each time.
+    pub fn encrypt(self: &Self, plaintext: &[u8]) -> Result<String> {
+        let (key, kdf) = match &self.secret {
+            Secret::Key(key) => (*key, None),
+            Secret::Passphrase(passphrase) => {
+                let mut salt = [0; SALT_LEN];
+                random(&mut salt)?;
+                let kdf = Kdf {
+                    algorithm: KDF.to_string(),
+                    iterations: self.iterations,
+                    salt: base64::encode(salt),
+                };
+                (derive(passphrase, &salt, self.iterations), Some(kdf))
+            }
+        };
+        let mut nonce = [0; NONCE_LEN];
+        random(&mut nonce)?;
+        let mut ciphertext = plaintext.to_vec();
+        sealing_key(&key)
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::empty(),
+                &mut ciphertext,
+            )
+            .map_err(|_| Error::BadInput("encryption failed".to_string()))?;
+        let envelope = Envelope {
+            encryption: ENCRYPTION.to_string(),
+            kdf: kdf,
+            nonce: base64::encode(nonce),
+            ciphertext: base64::encode(ciphertext),
+        };
+        serde_json::to_string_pretty(&envelope)
+            .map_err(|e| Error::BadInput(format!("couldn't serialize encrypted data: {}", e)))
+    }
+
+    /// decrypt returns the data encrypted by encrypt. An error is returned if the data was encrypted with a
+    /// different key or passphrase, or has been tampered with.
+    pub fn decrypt(self: &Self, encrypted: &str) -> Result<Vec<u8>> {
+        let malformed = |e: String| Error::BadInput(format!("malformed encrypted data: {}", e));
+        let envelope: Envelope =
+            serde_json::from_str(encrypted).map_err(|e| malformed(e.to_string()))?;
+        if envelope.encryption != ENCRYPTION {
+            return Err(malformed(format!(
+                "unsupported encryption {}",
+                envelope.encryption
+            )));
+        }
+        if let Some(kdf) = envelope.kdf.as_ref().filter(|kdf| kdf.algorithm != KDF) {
+            return Err(malformed(format!(
+                "unsupported key derivation {}",
+                kdf.algorithm
+            )));
+        }
+        let key = match (&self.secret, &envelope.kdf) {
+            (Secret::Key(key), None) => *key,
+            (Secret::Passphrase(passphrase), Some(kdf)) => {
+                let salt = base64::decode(&kdf.salt).map_err(|e| malformed(e.to_string()))?;
+                derive(passphrase, &salt, kdf.iterations)
+            }
+            (_, None) => {
+                return Err(Error::Auth(format!(
+                    "the data was encrypted with a key, so set {} instead of {}",
+                    KEY_VARIABLE, PASSPHRASE_VARIABLE
+                )))
+            }
+            (_, Some(_)) => {
+                return Err(Error::Auth(format!(
+                    "the data was encrypted with a passphrase, so set {} instead of {}",
+                    PASSPHRASE_VARIABLE, KEY_VARIABLE
+                )))
+            }
+        };
+        let mut nonce = [0; NONCE_LEN];
+        let nonce_bytes = base64::decode(&envelope.nonce).map_err(|e| malformed(e.to_string()))?;
+        if nonce_bytes.len() != NONCE_LEN {
+            return Err(malformed("invalid nonce".to_string()));
+        }
+        nonce.copy_from_slice(&nonce_bytes);
+        let mut data =
+            base64::decode(&envelope.ciphertext).map_err(|e| malformed(e.to_string()))?;
+        let plaintext = sealing_key(&key)
+            .open_in_place(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut data)
+            .map_err(|_| {
+                Error::Auth("decryption failed: the key or passphrase is wrong".to_string())
+            })?;
+        Ok(plaintext.to_vec())
+    }
+
+    /// is_encrypted returns true if and only if the given data is in the format written by encrypt.
+    pub fn is_encrypted(data: &str) -> bool {
+        serde_json::from_str::<Envelope>(data).is_ok()
+    }
+
+    #[cfg(test)]
+    fn with_iterations(self: Self, iterations: u32) -> Encryption {
+        Encryption {
+            iterations: iterations,
+            ..self
+        }
+    }
+}
+
+fn sealing_key(key: &[u8; KEY_LEN]) -> LessSafeKey {
+    LessSafeKey::new(UnboundKey::new(&CHACHA20_POLY1305, key).expect("key has the right length"))
+}
+
+fn derive(passphrase: &str, salt: &[u8], iterations: u32) -> [u8; KEY_LEN] {
+    let mut key = [0; KEY_LEN];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        NonZeroU32::new(iterations.max(1)).unwrap(),
+        salt,
+        passphrase.as_bytes(),
+        &mut key,
+    );
+    key
+}
+
+fn random(dest: &mut [u8]) -> Result<()> {
+    SystemRandom::new()
+        .fill(dest)
+        .map_err(|_| Error::BadInput("couldn't generate random data".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=";
+
+    #[test]
+    fn data_encrypted_with_a_key_can_be_decrypted() {
+        let encryption = Encryption::with_key(KEY).unwrap();
+        let encrypted = encryption.encrypt(b"refresh token").unwrap();
+        assert!(Encryption::is_encrypted(&encrypted));
+        assert!(!encrypted.contains("refresh token"));
+        assert_eq!(encryption.decrypt(&encrypted).unwrap(), b"refresh token");
+
+        // a new nonce is used each time
+        assert_ne!(encryption.encrypt(b"refresh token").unwrap(), encrypted);
+    }
+
+    #[test]
+    fn data_encrypted_with_a_passphrase_can_be_decrypted() {
+        let encryption = Encryption::with_passphrase("secret")
+            .unwrap()
+            .with_iterations(10);
+        let encrypted = encryption.encrypt(b"refresh token").unwrap();
+        assert_eq!(encryption.decrypt(&encrypted).unwrap(), b"refresh token");
+        assert!(matches!(
+            Encryption::with_passphrase("wrong")
+                .unwrap()
+                .decrypt(&encrypted),
+            Err(Error::Auth(_))
+        ));
+        assert!(matches!(
+            Encryption::with_key(KEY).unwrap().decrypt(&encrypted),
+            Err(Error::Auth(_))
+        ));
+    }
+
+    #[test]
+    fn tampered_data_is_rejected() {
+        let encryption = Encryption::with_key(KEY).unwrap();
+        let mut envelope: serde_json::Value =
+            serde_json::from_str(&encryption.encrypt(b"refresh token").unwrap()).unwrap();
+        envelope["ciphertext"] = base64::encode(b"another token, which is longer").into();
+        assert!(matches!(
+            encryption.decrypt(&envelope.to_string()),
+            Err(Error::Auth(_))
+        ));
+    }
+
+    #[test]
+    fn invalid_keys_are_rejected() {
+        assert!(matches!(
+            Encryption::with_key("not base64!"),
+            Err(Error::BadInput(_))
+        ));
+        assert!(matches!(
+            Encryption::with_key(&base64::encode(b"too short")),
+            Err(Error::BadInput(_))
+        ));
+        assert!(matches!(
+            Encryption::with_passphrase(""),
+            Err(Error::BadInput(_))
+        ));
+        assert!(!Encryption::is_encrypted("{}"));
+    }
+}
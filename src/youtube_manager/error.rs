@@ -0,0 +1,242 @@
+use google_youtube3::client;
+use std::{fmt, io};
+
+/// Error is the error type of the playlist manager.
+#[derive(Debug)]
+pub enum Error {
+    /// Auth indicates that authentication or authorisation failed.
+    Auth(String),
+    /// Api indicates that a call to the YouTube Data API failed.
+    Api(client::Error),
+    /// Quota indicates that the YouTube Data API quota has been used up.
+    Quota(String),
+    /// MalformedData indicates that data returned by the YouTube Data API could not be interpreted.
+    MalformedData(String),
+    /// BadInput indicates invalid input such as a command line argument with an invalid value.
+    BadInput(String),
+    /// Io indicates that an I/O operation, such as reading a file, failed.
+    Io(io::Error),
+    /// Conflict indicates that the playlist has changed since a plan for changing it was made.
+    Conflict(String),
+    /// Verification indicates that, after changes were made, the playlist was not in the expected state.
+    Verification(String),
+    /// Interrupted indicates that an earlier run was interrupted before it finished changing the playlist, so
+    /// no other changes may be made until it has been resumed or abandoned.
+    Interrupted(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// exit_code returns a distinct process exit code for each kind of error. (Exit code 1 is left for
+    /// command line usage errors, which are reported by clap.)
+    pub fn exit_code(self: &Self) -> i32 {
+        match self {
+            Error::BadInput(_) => 2,
+            Error::Auth(_) => 3,
+            Error::Api(_) => 4,
+            Error::Quota(_) => 5,
+            Error::MalformedData(_) => 6,
+            Error::Io(_) => 7,
+            Error::Conflict(_) => 8,
+            Error::Verification(_) => 9,
+            Error::Interrupted(_) => 10,
+        }
+    }
+
+    /// is_retryable returns true if and only if the error may be transient, so that the call which failed may
+    /// succeed if it is made again. Quota and authorisation failures are never retryable.
+    pub fn is_retryable(self: &Self) -> bool {
+        match self {
+            Error::Api(client::Error::HttpError(_)) => true,
+            Error::Api(client::Error::Failure(response)) => response.status().is_server_error(),
+            Error::Api(client::Error::BadRequest(value)) => {
+                value["error"]["code"]
+                    .as_u64()
+                    .map_or(false, |code| code >= 500)
+                    || has_reason(
+                        value,
+                        &[
+                            "backendError",
+                            "internalError",
+                            "rateLimitExceeded",
+                            "userRateLimitExceeded",
+                        ],
+                    )
+            }
+            _ => false,
+        }
+    }
+
+    /// is_not_found returns true if and only if the error reports that the resource the call referred to, such
+    /// as a playlist item, does not exist.
+    pub fn is_not_found(self: &Self) -> bool {
+        match self {
+            Error::Api(client::Error::Failure(response)) => response.status().as_u16() == 404,
+            Error::Api(client::Error::BadRequest(value)) => value["error"]["code"] == 404,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Auth(s) => write!(f, "authentication failed: {}", s),
+            Error::Api(e) => write!(f, "YouTube API call failed: {}", e),
+            Error::Quota(s) => write!(f, "YouTube API quota exceeded: {}", s),
+            Error::MalformedData(s) => write!(f, "malformed data from YouTube: {}", s),
+            Error::BadInput(s) => write!(f, "invalid input: {}", s),
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::Conflict(s) => write!(f, "playlist has changed: {}", s),
+            Error::Verification(s) => {
+                write!(f, "playlist is not as expected after the changes: {}", s)
+            }
+            Error::Interrupted(s) => write!(f, "an earlier run was interrupted: {}", s),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+/// Errors returned by the YouTube Data API are classified so that quota and authorisation failures can be
+/// distinguished from other failures.
+impl From<client::Error> for Error {
+    fn from(e: client::Error) -> Self {
+        match &e {
+            client::Error::MissingToken(err) => Error::Auth(err.to_string()),
+            client::Error::JsonDecodeError(body, err) => {
+                Error::MalformedData(format!("{}: {}", err, body))
+            }
+            client::Error::BadRequest(value) => {
+                let message = value["error"]["message"]
+                    .as_str()
+                    .unwrap_or("no message")
+                    .to_owned();
+                if has_reason(value, &["quotaExceeded", "dailyLimitExceeded"]) {
+                    Error::Quota(message)
+                } else if value["error"]["code"] == 401
+                    || has_reason(
+                        value,
+                        &["authError", "insufficientPermissions", "forbidden"],
+                    )
+                {
+                    Error::Auth(message)
+                } else {
+                    Error::Api(e)
+                }
+            }
+            client::Error::Failure(response) if response.status().as_u16() == 401 => {
+                Error::Auth(response.status().to_string())
+            }
+            _ => Error::Api(e),
+        }
+    }
+}
+
+/// has_reason returns true if and only if the given YouTube Data API error response gives one of the given
+/// reasons.
+fn has_reason(value: &serde_json::Value, reasons: &[&str]) -> bool {
+    value["error"]["errors"]
+        .as_array()
+        .map(|errors| {
+            errors
+                .iter()
+                .any(|e| reasons.iter().any(|reason| e["reason"] == *reason))
+        })
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn quota_errors_are_classified() {
+        let e = Error::from(bad_request(403, "quotaExceeded"));
+        assert!(matches!(e, Error::Quota(ref s) if s == "request failed"));
+    }
+
+    #[test]
+    fn auth_errors_are_classified() {
+        assert!(matches!(
+            Error::from(bad_request(401, "required")),
+            Error::Auth(_)
+        ));
+        assert!(matches!(
+            Error::from(bad_request(403, "insufficientPermissions")),
+            Error::Auth(_)
+        ));
+    }
+
+    #[test]
+    fn other_errors_are_api_errors() {
+        assert!(matches!(
+            Error::from(bad_request(404, "playlistNotFound")),
+            Error::Api(_)
+        ));
+        assert!(matches!(
+            Error::from(bad_request(500, "backendError")),
+            Error::Api(_)
+        ));
+        assert!(matches!(
+            Error::from(client::Error::Cancelled),
+            Error::Api(_)
+        ));
+    }
+
+    #[test]
+    fn transient_errors_are_retryable() {
+        assert!(Error::from(bad_request(500, "backendError")).is_retryable());
+        assert!(Error::from(bad_request(503, "serviceUnavailable")).is_retryable());
+        assert!(Error::from(bad_request(403, "rateLimitExceeded")).is_retryable());
+        assert!(!Error::from(bad_request(403, "quotaExceeded")).is_retryable());
+        assert!(!Error::from(bad_request(401, "required")).is_retryable());
+        assert!(!Error::from(bad_request(404, "playlistNotFound")).is_retryable());
+        assert!(!Error::Conflict(String::new()).is_retryable());
+    }
+
+    #[test]
+    fn not_found_errors_are_recognised() {
+        assert!(Error::from(bad_request(404, "playlistItemNotFound")).is_not_found());
+        assert!(!Error::from(bad_request(500, "backendError")).is_not_found());
+        assert!(!Error::Conflict(String::new()).is_not_found());
+    }
+
+    #[test]
+    fn exit_codes_are_distinct() {
+        let mut codes = vec![
+            Error::Auth(String::new()).exit_code(),
+            Error::Api(client::Error::Cancelled).exit_code(),
+            Error::Quota(String::new()).exit_code(),
+            Error::MalformedData(String::new()).exit_code(),
+            Error::BadInput(String::new()).exit_code(),
+            Error::Io(io::Error::new(io::ErrorKind::Other, "")).exit_code(),
+            Error::Conflict(String::new()).exit_code(),
+            Error::Verification(String::new()).exit_code(),
+            Error::Interrupted(String::new()).exit_code(),
+        ];
+        let n = codes.len();
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes.len(), n);
+        assert!(!codes.contains(&0) && !codes.contains(&1));
+    }
+
+    fn bad_request(code: u16, reason: &str) -> client::Error {
+        client::Error::BadRequest(json!({
+            "error": {
+                "code": code,
+                "message": "request failed",
+                "errors": [{"domain": "youtube", "reason": reason}]
+            }
+        }))
+    }
+}
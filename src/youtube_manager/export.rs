@@ -0,0 +1,464 @@
+use super::error::{Error, Result};
+use super::playlist::{Item, ItemProperties};
+use super::sort::Category;
+use chrono::{DateTime, Utc};
+use std::{io, str::FromStr};
+
+/// ExportFormat is a format in which the items of a playlist can be exported.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ExportFormat {
+    /// Csv is comma-separated values with a header row, suitable for spreadsheets.
+    Csv,
+    /// Markdown is a Markdown table.
+    Markdown,
+    /// Ics is an iCalendar file with an event for each scheduled stream.
+    Ics,
+    /// Atom is an Atom feed with an entry for each viewable or scheduled video.
+    Atom,
+    /// Rss is an RSS 2.0 feed with an item for each viewable or scheduled video.
+    Rss,
+}
+
+impl FromStr for ExportFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "csv" => Ok(ExportFormat::Csv),
+            "markdown" => Ok(ExportFormat::Markdown),
+            "ics" => Ok(ExportFormat::Ics),
+            "atom" => Ok(ExportFormat::Atom),
+            "rss" => Ok(ExportFormat::Rss),
+            _ => Err(Error::BadInput(format!("invalid export format {}", s))),
+        }
+    }
+}
+
+const HEADERS: [&str; 5] = ["Position", "Title", "State", "Time", "URL"];
+
+/// URL_COLUMN is the index of the URL in each row.
+const URL_COLUMN: usize = 4;
+
+/// export returns the given items of the playlist with the given id in the given format. In the table
+/// formats, each item gives its position in the playlist, title, state (streamed, uploaded, scheduled,
+/// blocked, or invalid), the time it was streamed, uploaded, or scheduled for, in the item's timezone, and the
+/// URL of the video. In iCalendar format, only scheduled items are exported. In the feed formats, only
+/// viewable and scheduled items are exported, in playlist order.
+pub fn export(playlist_id: &str, items: &[Item], format: ExportFormat) -> Result<String> {
+    let rows = items.iter().enumerate().map(|(n, i)| {
+        let (state, time) = i.state();
+        [
+            (n + 1).to_string(),
+            i.title.clone(),
+            state.to_string(),
+            time.unwrap_or_default(),
+            video_url(&i.video_id),
+        ]
+    });
+    match format {
+        ExportFormat::Csv => {
+            let mut w = csv::Writer::from_writer(vec![]);
+            w.write_record(&HEADERS).map_err(csv_error)?;
+            for row in rows {
+                w.write_record(&row).map_err(csv_error)?;
+            }
+            let bytes = w.into_inner().map_err(|e| csv_error(e.into_error()))?;
+            Ok(String::from_utf8(bytes).expect("CSV of strings is valid UTF-8"))
+        }
+        ExportFormat::Markdown => {
+            let mut table = format!("| {} |\n", HEADERS.join(" | "));
+            table.push_str(&format!("|{}\n", "---|".repeat(HEADERS.len())));
+            for row in rows {
+                // The URL is written as an autolink, since escaping it would break the link.
+                let cells: Vec<String> = row
+                    .iter()
+                    .enumerate()
+                    .map(|(n, cell)| {
+                        if n == URL_COLUMN {
+                            format!("<{}>", cell)
+                        } else {
+                            escape_markdown(cell)
+                        }
+                    })
+                    .collect();
+                table.push_str(&format!("| {} |\n", cells.join(" | ")));
+            }
+            Ok(table)
+        }
+        ExportFormat::Ics => Ok(ics(items, Utc::now())),
+        ExportFormat::Atom => Ok(atom(playlist_id, items)),
+        ExportFormat::Rss => Ok(rss(playlist_id, items)),
+    }
+}
+
+/// feed_entries returns the title, URL, and publication time of each item which belongs in a feed. The
+/// publication time is the time a video was streamed or uploaded or, for a scheduled video, the time it is
+/// scheduled for.
+fn feed_entries(items: &[Item]) -> Vec<(&str, String, DateTime<Utc>)> {
+    items
+        .iter()
+        .filter_map(|i| {
+            i.viewable_time()
+                .or_else(|| match Category::of(i) {
+                    Category::Scheduled => i.scheduled_start_time,
+                    _ => None,
+                })
+                .map(|t| (i.title.as_str(), video_url(&i.video_id), t))
+        })
+        .collect()
+}
+
+/// atom returns an Atom feed of the given items of the playlist with the given id. The feed is updated when
+/// its most recent entry is published.
+fn atom(playlist_id: &str, items: &[Item]) -> String {
+    let entries = feed_entries(items);
+    let url = escape_xml(&playlist_url(playlist_id));
+    let updated = entries.iter().map(|(_, _, t)| *t).max();
+    let mut feed = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <feed xmlns=\"http://www.w3.org/2005/Atom\">\n\
+         \x20 <title>YouTube playlist {}</title>\n\
+         \x20 <link href=\"{}\"/>\n\
+         \x20 <id>{}</id>\n\
+         \x20 <updated>{}</updated>\n\
+         \x20 <author><name>youtube-playlist-manager</name></author>\n",
+        escape_xml(playlist_id),
+        url,
+        url,
+        updated.unwrap_or_else(Utc::now).to_rfc3339()
+    );
+    for (title, url, t) in entries {
+        feed.push_str(&format!(
+            "  <entry>\n\
+             \x20   <title>{}</title>\n\
+             \x20   <link href=\"{}\"/>\n\
+             \x20   <id>{}</id>\n\
+             \x20   <published>{}</published>\n\
+             \x20   <updated>{}</updated>\n\
+             \x20 </entry>\n",
+            escape_xml(title),
+            escape_xml(&url),
+            escape_xml(&url),
+            t.to_rfc3339(),
+            t.to_rfc3339()
+        ));
+    }
+    feed.push_str("</feed>\n");
+    feed
+}
+
+/// rss returns an RSS 2.0 feed of the given items of the playlist with the given id.
+fn rss(playlist_id: &str, items: &[Item]) -> String {
+    let mut feed = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <rss version=\"2.0\">\n\
+         <channel>\n\
+         \x20 <title>YouTube playlist {}</title>\n\
+         \x20 <link>{}</link>\n\
+         \x20 <description>Videos in YouTube playlist {}</description>\n",
+        escape_xml(playlist_id),
+        escape_xml(&playlist_url(playlist_id)),
+        escape_xml(playlist_id)
+    );
+    for (title, url, t) in feed_entries(items) {
+        feed.push_str(&format!(
+            "  <item>\n\
+             \x20   <title>{}</title>\n\
+             \x20   <link>{}</link>\n\
+             \x20   <guid isPermaLink=\"true\">{}</guid>\n\
+             \x20   <pubDate>{}</pubDate>\n\
+             \x20 </item>\n",
+            escape_xml(title),
+            escape_xml(&url),
+            escape_xml(&url),
+            t.to_rfc2822()
+        ));
+    }
+    feed.push_str("</channel>\n</rss>\n");
+    feed
+}
+
+/// playlist_url returns the URL of the playlist with the given id.
+fn playlist_url(playlist_id: &str) -> String {
+    format!("https://www.youtube.com/playlist?list={}", playlist_id)
+}
+
+/// escape_xml escapes the characters with special meanings in XML text and attribute values.
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// EVENT_DURATION is the duration of the calendar event for a scheduled stream. Streams have no scheduled end,
+/// so each event is given a nominal hour rather than no length at all.
+const EVENT_DURATION: &str = "PT1H";
+
+/// ics returns an iCalendar file with an event for each scheduled item which has not started yet. The
+/// event's UID is derived from the video id so that calendar apps recognise the same stream across exports.
+/// now is the time the file is created. Streams which were scheduled to start before then, but have not
+/// started, are left out since they have been missed.
+fn ics(items: &[Item], now: DateTime<Utc>) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//glyn//youtube-playlist-manager//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+    ];
+    for i in items {
+        let start = match (Category::of(i), i.scheduled_start_time) {
+            (Category::Scheduled, Some(start)) if start > now => start,
+            _ => continue,
+        };
+        let url = video_url(&i.video_id);
+        lines.extend(vec![
+            "BEGIN:VEVENT".to_string(),
+            format!("UID:{}@youtube.com", i.video_id),
+            format!("DTSTAMP:{}", ics_time(now)),
+            format!("DTSTART:{}", ics_time(start)),
+            format!("DURATION:{}", EVENT_DURATION),
+            format!("SUMMARY:{}", escape_ics(&i.title)),
+            format!("DESCRIPTION:{}", escape_ics(&url)),
+            format!("URL:{}", url),
+            "END:VEVENT".to_string(),
+        ]);
+    }
+    lines.push("END:VCALENDAR".to_string());
+    lines.iter().map(|line| fold_ics(line)).collect()
+}
+
+fn ics_time(t: DateTime<Utc>) -> String {
+    t.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// escape_ics escapes the characters with special meanings in iCalendar text values.
+fn escape_ics(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\r', "\\n")
+        .replace('\n', "\\n")
+}
+
+/// fold_ics splits the given content line into lines of at most 75 octets, as iCalendar requires, and
+/// terminates each line with CRLF. Continuation lines start with a space.
+fn fold_ics(line: &str) -> String {
+    let mut folded = String::new();
+    let mut len = 0;
+    for c in line.chars() {
+        if len + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            len = 1;
+        }
+        folded.push(c);
+        len += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+/// video_url returns the URL for watching the video with the given id.
+pub fn video_url(video_id: &str) -> String {
+    format!("https://www.youtube.com/watch?v={}", video_id)
+}
+
+/// escape_markdown escapes characters which would otherwise be interpreted as Markdown in a table cell.
+fn escape_markdown(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        if "\\|*_`[]<>".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn csv_error<E: Into<io::Error>>(e: E) -> Error {
+    Error::Io(e.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::Tz;
+
+    #[test]
+    fn export_csv() {
+        assert_eq!(
+            export("pl1", &new_items(), ExportFormat::Csv).unwrap(),
+            "Position,Title,State,Time,URL\n\
+             1,\"Live, again\",streamed,\"Thu, 30 Sep 2021 10:56:07 +0000\",https://www.youtube.com/watch?v=v1\n\
+             2,Gone,invalid,,https://www.youtube.com/watch?v=v2\n"
+        );
+    }
+
+    #[test]
+    fn export_markdown() {
+        assert_eq!(
+            export("pl1", &new_items(), ExportFormat::Markdown).unwrap(),
+            "| Position | Title | State | Time | URL |\n\
+             |---|---|---|---|---|\n\
+             | 1 | Live, again | streamed | Thu, 30 Sep 2021 10:56:07 +0000 | <https://www.youtube.com/watch?v=v1> |\n\
+             | 2 | Gone | invalid |  | <https://www.youtube.com/watch?v=v2> |\n"
+        );
+    }
+
+    #[test]
+    fn markdown_urls_are_not_escaped() {
+        let mut item = Item::default();
+        item.video_id = "ab_cd".to_string();
+        item.title = "snake_case".to_string();
+        let table = export("pl1", &[item], ExportFormat::Markdown).unwrap();
+        assert!(table.ends_with(
+            "| 1 | snake\\_case | invalid |  | <https://www.youtube.com/watch?v=ab_cd> |\n"
+        ));
+    }
+
+    #[test]
+    fn export_ics() {
+        let mut scheduled = Item::default();
+        scheduled.video_id = "v3".to_string();
+        scheduled.title = "Next week; live".to_string();
+        scheduled.scheduled_start_time = Some("2021-10-07T18:30:00Z".parse().unwrap());
+        let mut missed = Item::default();
+        missed.video_id = "v4".to_string();
+        missed.title = "Missed".to_string();
+        missed.scheduled_start_time = Some("2021-09-07T18:30:00Z".parse().unwrap());
+        let mut items = new_items();
+        items.push(scheduled);
+        items.push(missed);
+
+        assert_eq!(
+            ics(&items, "2021-10-01T12:00:00Z".parse().unwrap()),
+            "BEGIN:VCALENDAR\r\n\
+             VERSION:2.0\r\n\
+             PRODID:-//glyn//youtube-playlist-manager//EN\r\n\
+             CALSCALE:GREGORIAN\r\n\
+             BEGIN:VEVENT\r\n\
+             UID:v3@youtube.com\r\n\
+             DTSTAMP:20211001T120000Z\r\n\
+             DTSTART:20211007T183000Z\r\n\
+             DURATION:PT1H\r\n\
+             SUMMARY:Next week\\; live\r\n\
+             DESCRIPTION:https://www.youtube.com/watch?v=v3\r\n\
+             URL:https://www.youtube.com/watch?v=v3\r\n\
+             END:VEVENT\r\n\
+             END:VCALENDAR\r\n"
+        );
+    }
+
+    #[test]
+    fn export_atom() {
+        assert_eq!(
+            atom("pl1", &new_feed_items()),
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>YouTube playlist pl1</title>
+  <link href="https://www.youtube.com/playlist?list=pl1"/>
+  <id>https://www.youtube.com/playlist?list=pl1</id>
+  <updated>2021-10-07T18:30:00+00:00</updated>
+  <author><name>youtube-playlist-manager</name></author>
+  <entry>
+    <title>Live, again</title>
+    <link href="https://www.youtube.com/watch?v=v1"/>
+    <id>https://www.youtube.com/watch?v=v1</id>
+    <published>2021-09-30T10:56:07+00:00</published>
+    <updated>2021-09-30T10:56:07+00:00</updated>
+  </entry>
+  <entry>
+    <title>Q&amp;A</title>
+    <link href="https://www.youtube.com/watch?v=v3"/>
+    <id>https://www.youtube.com/watch?v=v3</id>
+    <published>2021-10-07T18:30:00+00:00</published>
+    <updated>2021-10-07T18:30:00+00:00</updated>
+  </entry>
+</feed>
+"#
+        );
+    }
+
+    #[test]
+    fn export_rss() {
+        assert_eq!(
+            rss("pl1", &new_feed_items()),
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<rss version="2.0">
+<channel>
+  <title>YouTube playlist pl1</title>
+  <link>https://www.youtube.com/playlist?list=pl1</link>
+  <description>Videos in YouTube playlist pl1</description>
+  <item>
+    <title>Live, again</title>
+    <link>https://www.youtube.com/watch?v=v1</link>
+    <guid isPermaLink="true">https://www.youtube.com/watch?v=v1</guid>
+    <pubDate>Thu, 30 Sep 2021 10:56:07 +0000</pubDate>
+  </item>
+  <item>
+    <title>Q&amp;A</title>
+    <link>https://www.youtube.com/watch?v=v3</link>
+    <guid isPermaLink="true">https://www.youtube.com/watch?v=v3</guid>
+    <pubDate>Thu, 7 Oct 2021 18:30:00 +0000</pubDate>
+  </item>
+</channel>
+</rss>
+"#
+        );
+    }
+
+    #[test]
+    fn long_ics_lines_are_folded() {
+        let line = "x".repeat(100);
+        assert_eq!(
+            fold_ics(&line),
+            format!("{}\r\n {}\r\n", "x".repeat(75), "x".repeat(25))
+        );
+    }
+
+    #[test]
+    fn ics_line_breaks_are_escaped() {
+        assert_eq!(escape_ics("a\r\nb\rc\nd"), "a\\nb\\nc\\nd");
+    }
+
+    #[test]
+    fn markdown_cells_are_escaped() {
+        assert_eq!(escape_markdown("a | *b*"), "a \\| \\*b\\*");
+    }
+
+    #[test]
+    fn parse_export_formats() {
+        assert_eq!("csv".parse::<ExportFormat>().unwrap(), ExportFormat::Csv);
+        assert!(matches!(
+            "pdf".parse::<ExportFormat>(),
+            Err(Error::BadInput(_))
+        ));
+    }
+
+    /// new_feed_items returns a streamed item, a deleted item, and a scheduled item.
+    fn new_feed_items() -> Vec<Item> {
+        let mut scheduled = Item::default();
+        scheduled.video_id = "v3".to_string();
+        scheduled.title = "Q&A".to_string();
+        scheduled.scheduled_start_time = Some("2021-10-07T18:30:00Z".parse().unwrap());
+        let mut items = new_items();
+        items.push(scheduled);
+        items
+    }
+
+    fn new_items() -> Vec<Item> {
+        let mut streamed = Item::default();
+        streamed.video_id = "v1".to_string();
+        streamed.title = "Live, again".to_string();
+        streamed.scheduled_start_time = Some("2021-09-30T10:00:00Z".parse().unwrap());
+        streamed.actual_start_time = Some("2021-09-30T10:56:07Z".parse().unwrap());
+        streamed.timezone = Some(Tz::UTC);
+
+        let mut deleted = Item::default();
+        deleted.video_id = "v2".to_string();
+        deleted.title = "Gone".to_string();
+        vec![streamed, deleted]
+    }
+}
@@ -0,0 +1,208 @@
+use super::error::{Error, Result};
+use super::plan::Plan;
+use super::playlist::Item;
+use super::quota::{Operation, Usage};
+use super::reorder::{self, Move};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+/// Journal records the progress of applying a plan so that, if the run is interrupted, it can be resumed
+/// later. Every step is recorded before any change is made and each step is marked as done once it has been
+/// made.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Journal {
+    /// plan is the plan being applied.
+    pub plan: Plan,
+    /// steps are the changes which apply the plan, in the order they are made.
+    pub steps: Vec<Step>,
+}
+
+/// Step is a single change to a playlist, together with whether it has been made.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Step {
+    pub action: Action,
+    pub done: bool,
+}
+
+/// Action is a change to a playlist.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Action {
+    /// Archive adds the video of an item which is about to be removed to the archive playlist, at the given
+    /// position or, if there is none, at the end.
+    Archive {
+        archive_to: String,
+        item: Item,
+        reason: String,
+        #[serde(default)]
+        position: Option<u32>,
+    },
+    /// Remove removes an item from the playlist.
+    Remove { item: Item, reason: String },
+    /// Move moves an item to a new position in the playlist.
+    Move(Move),
+}
+
+impl Action {
+    /// operation returns the YouTube API operation which makes the change.
+    pub fn operation(self: &Self) -> Operation {
+        match self {
+            Action::Archive { .. } => Operation::Insert,
+            Action::Remove { .. } => Operation::Delete,
+            Action::Move(_) => Operation::Update,
+        }
+    }
+}
+
+impl Journal {
+    /// new constructs a journal of the given plan, which is applied by the given actions, none of which
+    /// have been made.
+    pub fn new(plan: &Plan, actions: Vec<Action>) -> Journal {
+        Journal {
+            plan: plan.clone(),
+            steps: actions
+                .into_iter()
+                .map(|a| Step {
+                    action: a,
+                    done: false,
+                })
+                .collect(),
+        }
+    }
+
+    /// next returns the index of the first step which has not been made, or the number of steps if they
+    /// have all been made.
+    pub fn next(self: &Self) -> usize {
+        self.steps
+            .iter()
+            .position(|s| !s.done)
+            .unwrap_or_else(|| self.steps.len())
+    }
+
+    /// remaining returns the YouTube API writes needed to make the steps which have not been made.
+    pub fn remaining(self: &Self) -> Usage {
+        let mut usage = Usage::default();
+        for s in self.steps.iter().filter(|s| !s.done) {
+            usage.add(s.action.operation(), 1);
+        }
+        usage
+    }
+
+    /// expected returns the items of the playlist, in order, after the first n steps have been made.
+    pub fn expected(self: &Self, n: usize) -> Vec<Item> {
+        let mut items = self.plan.items.clone();
+        for s in &self.steps[..n.min(self.steps.len())] {
+            match &s.action {
+                Action::Archive { .. } => {}
+                Action::Remove { item, .. } => {
+                    items.retain(|i| i.playlist_item_id != item.playlist_item_id)
+                }
+                Action::Move(m) => reorder::apply(&mut items, std::slice::from_ref(m)),
+            }
+        }
+        items
+    }
+
+    /// save writes the journal to the given file in JSON format. The file is replaced in a single step so that
+    /// an interruption cannot leave it partly written.
+    pub fn save(self: &Self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| Error::BadInput(format!("couldn't serialize journal: {}", e)))?;
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+        fs::write(&partial, json)?;
+        fs::rename(&partial, path)?;
+        Ok(())
+    }
+
+    /// load reads a journal from the given file, which must have been written by save.
+    pub fn load(path: &Path) -> Result<Journal> {
+        let json = fs::read_to_string(path)?;
+        serde_json::from_str(&json)
+            .map_err(|e| Error::BadInput(format!("invalid journal file {}: {}", path.display(), e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::plan::Deletion;
+    use super::super::testing::new_item;
+    use super::*;
+
+    #[test]
+    fn expected_makes_the_first_steps() {
+        let journal = new_journal();
+        assert_eq!(ids(&journal.expected(0)), vec!["pii1", "pii2", "pii3"]);
+        assert_eq!(ids(&journal.expected(1)), vec!["pii1", "pii2", "pii3"]);
+        assert_eq!(ids(&journal.expected(2)), vec!["pii1", "pii3"]);
+        assert_eq!(ids(&journal.expected(3)), vec!["pii3", "pii1"]);
+        assert_eq!(journal.expected(3), journal.plan.result());
+    }
+
+    #[test]
+    fn next_and_remaining_skip_steps_which_are_done() {
+        let mut journal = new_journal();
+        assert_eq!(journal.next(), 0);
+        assert_eq!(journal.remaining().units(), 150);
+
+        journal.steps[0].done = true;
+        journal.steps[1].done = true;
+        assert_eq!(journal.next(), 2);
+        assert_eq!(journal.remaining().units(), 50);
+
+        journal.steps[2].done = true;
+        assert_eq!(journal.next(), 3);
+        assert_eq!(journal.remaining().units(), 0);
+    }
+
+    #[test]
+    fn save_and_load() {
+        let mut journal = new_journal();
+        journal.steps[0].done = true;
+        let path = std::env::temp_dir().join(format!("journal-test-{}.json", std::process::id()));
+        journal.save(&path).unwrap();
+        let loaded = Journal::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), journal);
+    }
+
+    fn new_journal() -> Journal {
+        let items: Vec<Item> = (1..=3).map(new_item).collect();
+        let deletion = Deletion {
+            item: items[1].clone(),
+            reason: "surplus".to_string(),
+        };
+        let m = Move {
+            item: items[2].clone(),
+            position: 0,
+        };
+        let plan = Plan {
+            playlist_id: "pl1".to_string(),
+            items: items.clone(),
+            deletions: vec![deletion.clone()],
+            moves: vec![m.clone()],
+            archive_to: Some("pl2".to_string()),
+            sort: Default::default(),
+        };
+        Journal::new(
+            &plan,
+            vec![
+                Action::Archive {
+                    archive_to: "pl2".to_string(),
+                    item: deletion.item.clone(),
+                    reason: deletion.reason.clone(),
+                    position: None,
+                },
+                Action::Remove {
+                    item: deletion.item,
+                    reason: deletion.reason,
+                },
+                Action::Move(m),
+            ],
+        )
+    }
+
+    fn ids(items: &[Item]) -> Vec<&str> {
+        items.iter().map(|i| i.playlist_item_id.as_str()).collect()
+    }
+}
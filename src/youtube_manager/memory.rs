@@ -0,0 +1,318 @@
+use super::backend::{PlaylistBackend, MAX_VIDEO_IDS};
+use super::error::{Error, Result};
+use async_trait::async_trait;
+use google_youtube3::{
+    api::{
+        PlaylistItem, PlaylistItemContentDetails, PlaylistItemListResponse, PlaylistItemSnippet,
+        Video,
+    },
+    client,
+};
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// DEFAULT_PAGE_SIZE is the number of playlist items per page which the YouTube backend asks for.
+const DEFAULT_PAGE_SIZE: usize = MAX_VIDEO_IDS;
+
+/// InMemoryBackend is a PlaylistBackend which holds playlists and videos in memory. It behaves like the
+/// YouTube Data API closely enough to exercise the playlist logic without a network.
+///
+/// Clones share the same state, so a clone can be used to inspect the playlists after they have been
+/// manipulated through another clone.
+#[derive(Clone)]
+pub struct InMemoryBackend {
+    state: Arc<Mutex<State>>,
+    page_size: usize,
+}
+
+#[derive(Default)]
+struct State {
+    playlists: HashMap<String, Vec<PlaylistItem>>,
+    videos: HashMap<String, Video>,
+    next_playlist_item_id: usize,
+    /// version is incremented whenever a playlist changes. It is used to make ETags for pages of playlist items.
+    version: usize,
+    video_lookups: usize,
+    /// pages_listed is the number of pages of playlist items returned, not counting unmodified pages.
+    pages_listed: usize,
+    position_updates: usize,
+    /// ignore_moves is true if position updates are accepted, and counted, without being applied.
+    ignore_moves: bool,
+}
+
+impl InMemoryBackend {
+    /// new constructs an empty in-memory backend.
+    pub fn new() -> InMemoryBackend {
+        InMemoryBackend {
+            state: Arc::new(Mutex::new(Default::default())),
+            page_size: DEFAULT_PAGE_SIZE,
+        }
+    }
+
+    /// with_page_size sets the number of playlist items returned per page.
+    pub fn with_page_size(self: Self, page_size: usize) -> InMemoryBackend {
+        assert!(page_size > 0);
+        InMemoryBackend {
+            page_size: page_size,
+            ..self
+        }
+    }
+
+    /// add_playlist creates an empty playlist with the given id, if the playlist does not already exist.
+    pub fn add_playlist(self: &Self, playlist_id: &str) {
+        self.state
+            .lock()
+            .unwrap()
+            .playlists
+            .entry(playlist_id.to_owned())
+            .or_default();
+    }
+
+    /// add_item appends an item for the given video to the given playlist and returns the playlist item id.
+    /// video_published_at, if present, must be in RFC3339 format.
+    pub fn add_item(
+        self: &Self,
+        playlist_id: &str,
+        video_id: &str,
+        title: &str,
+        video_published_at: Option<String>,
+    ) -> String {
+        let mut state = self.state.lock().unwrap();
+        let item = new_playlist_item(&mut state, playlist_id, video_id, title, video_published_at);
+        let playlist_item_id = item.id.clone().unwrap();
+        state.version += 1;
+        state
+            .playlists
+            .entry(playlist_id.to_owned())
+            .or_default()
+            .push(item);
+        playlist_item_id
+    }
+
+    /// add_video records the details of a video. The video must have an id. The title and published time
+    /// in the video's snippet, if any, are used when the video is inserted into a playlist.
+    pub fn add_video(self: &Self, video: Video) {
+        let id = video.id.clone().expect("video must have an id");
+        self.state.lock().unwrap().videos.insert(id, video);
+    }
+
+    /// video_lookups returns the number of calls which have been made to look up videos.
+    pub fn video_lookups(self: &Self) -> usize {
+        self.state.lock().unwrap().video_lookups
+    }
+
+    /// pages_listed returns the number of pages of playlist items which have been listed, not counting pages
+    /// which were reported as not modified.
+    pub fn pages_listed(self: &Self) -> usize {
+        self.state.lock().unwrap().pages_listed
+    }
+
+    /// position_updates returns the number of calls which have been made to move playlist items.
+    pub fn position_updates(self: &Self) -> usize {
+        self.state.lock().unwrap().position_updates
+    }
+
+    /// ignore_moves makes the backend accept position updates without applying them, to mimic YouTube not
+    /// applying them as requested.
+    pub fn ignore_moves(self: &Self) {
+        self.state.lock().unwrap().ignore_moves = true;
+    }
+
+    /// video_ids returns the ids of the videos in the given playlist, in playlist order.
+    pub fn video_ids(self: &Self, playlist_id: &str) -> Vec<String> {
+        self.state
+            .lock()
+            .unwrap()
+            .playlists
+            .get(playlist_id)
+            .map(|items| items.iter().map(|item| video_id(item)).collect())
+            .unwrap_or_default()
+    }
+}
+
+#[async_trait]
+impl PlaylistBackend for InMemoryBackend {
+    async fn list_items(
+        self: &Self,
+        playlist_id: &str,
+        page_token: &Option<String>,
+        etag: Option<&str>,
+    ) -> Result<Option<PlaylistItemListResponse>> {
+        let mut state = self.state.lock().unwrap();
+        let items = state
+            .playlists
+            .get(playlist_id)
+            .ok_or_else(|| not_found("playlistNotFound", playlist_id))?;
+        let start = match page_token {
+            Some(token) => token
+                .parse::<usize>()
+                .map_err(|_| bad_request("invalidPageToken", token))?,
+            None => 0,
+        };
+        let page_etag = format!("{}-{}", state.version, start);
+        if etag == Some(&page_etag) {
+            return Ok(None);
+        }
+        let end = items.len().min(start + self.page_size);
+        let res = PlaylistItemListResponse {
+            etag: Some(page_etag),
+            items: Some(items[start.min(end)..end].to_vec()),
+            next_page_token: if end < items.len() {
+                Some(end.to_string())
+            } else {
+                None
+            },
+            ..Default::default()
+        };
+        state.pages_listed += 1;
+        Ok(Some(res))
+    }
+
+    async fn videos(self: &Self, video_ids: &[String]) -> Result<Vec<Video>> {
+        if video_ids.len() > MAX_VIDEO_IDS {
+            return Err(bad_request(
+                "tooManyIds",
+                &format!("at most {} video ids may be looked up", MAX_VIDEO_IDS),
+            ));
+        }
+        let mut state = self.state.lock().unwrap();
+        state.video_lookups += 1;
+        Ok(video_ids
+            .iter()
+            .filter_map(|id| state.videos.get(id).cloned())
+            .collect())
+    }
+
+    async fn update_position(
+        self: &Self,
+        playlist_id: &str,
+        playlist_item_id: &str,
+        _video_id: &str,
+        position: u32,
+    ) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.ignore_moves {
+            state.position_updates += 1;
+            return Ok(());
+        }
+        let items = state
+            .playlists
+            .get_mut(playlist_id)
+            .ok_or_else(|| not_found("playlistNotFound", playlist_id))?;
+        let n = index_of(items, playlist_item_id)?;
+        let item = items.remove(n);
+        let position = (position as usize).min(items.len());
+        items.insert(position, item);
+        state.position_updates += 1;
+        state.version += 1;
+        Ok(())
+    }
+
+    async fn insert(
+        self: &Self,
+        playlist_id: &str,
+        video_id: &str,
+        position: Option<u32>,
+    ) -> Result<String> {
+        let mut state = self.state.lock().unwrap();
+        let snippet = state
+            .videos
+            .get(video_id)
+            .ok_or_else(|| not_found("videoNotFound", video_id))?
+            .snippet
+            .clone()
+            .unwrap_or_default();
+        let item = new_playlist_item(
+            &mut state,
+            playlist_id,
+            video_id,
+            snippet.title.as_deref().unwrap_or(video_id),
+            snippet.published_at,
+        );
+        let playlist_item_id = item.id.clone().unwrap();
+        let items = state
+            .playlists
+            .get_mut(playlist_id)
+            .ok_or_else(|| not_found("playlistNotFound", playlist_id))?;
+        let position = position
+            .map(|p| (p as usize).min(items.len()))
+            .unwrap_or(items.len());
+        items.insert(position, item);
+        state.version += 1;
+        Ok(playlist_item_id)
+    }
+
+    async fn delete(self: &Self, playlist_item_id: &str) -> Result<()> {
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+        for items in state.playlists.values_mut() {
+            if let Ok(n) = index_of(items, playlist_item_id) {
+                items.remove(n);
+                state.version += 1;
+                return Ok(());
+            }
+        }
+        Err(not_found("playlistItemNotFound", playlist_item_id))
+    }
+}
+
+fn new_playlist_item(
+    state: &mut State,
+    playlist_id: &str,
+    video_id: &str,
+    title: &str,
+    video_published_at: Option<String>,
+) -> PlaylistItem {
+    state.next_playlist_item_id += 1;
+    PlaylistItem {
+        id: Some(format!("pi{}", state.next_playlist_item_id)),
+        snippet: Some(PlaylistItemSnippet {
+            playlist_id: Some(playlist_id.to_owned()),
+            title: Some(title.to_owned()),
+            ..Default::default()
+        }),
+        content_details: Some(PlaylistItemContentDetails {
+            video_id: Some(video_id.to_owned()),
+            video_published_at: video_published_at,
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+fn index_of(items: &Vec<PlaylistItem>, playlist_item_id: &str) -> Result<usize> {
+    items
+        .iter()
+        .position(|item| item.id.as_deref() == Some(playlist_item_id))
+        .ok_or_else(|| not_found("playlistItemNotFound", playlist_item_id))
+}
+
+fn video_id(item: &PlaylistItem) -> String {
+    item.content_details
+        .as_ref()
+        .and_then(|details| details.video_id.clone())
+        .unwrap_or_default()
+}
+
+/// not_found returns the error the YouTube Data API returns when a resource cannot be found.
+fn not_found(reason: &str, id: &str) -> Error {
+    api_error(404, reason, &format!("{} not found", id))
+}
+
+/// bad_request returns the error the YouTube Data API returns when a request is invalid.
+fn bad_request(reason: &str, message: &str) -> Error {
+    api_error(400, reason, message)
+}
+
+fn api_error(code: u16, reason: &str, message: &str) -> Error {
+    Error::from(client::Error::BadRequest(json!({
+        "error": {
+            "code": code,
+            "message": message,
+            "errors": [{"domain": "youtube", "reason": reason}]
+        }
+    })))
+}
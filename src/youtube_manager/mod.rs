@@ -1 +1,22 @@
+pub mod backend;
+pub mod config;
+pub mod encryption;
+pub mod error;
+pub mod export;
+pub mod journal;
+pub mod memory;
+pub mod output;
+pub mod plan;
 pub mod playlist;
+pub mod profile;
+pub mod quota;
+pub mod reorder;
+pub mod retry;
+pub mod rules;
+pub mod snapshot;
+pub mod sort;
+#[cfg(test)]
+mod testing;
+pub mod tokens;
+pub mod watch;
+pub mod youtube;
@@ -0,0 +1,218 @@
+use super::error::{Error, Result};
+use super::playlist::Item;
+use super::reorder::Move;
+use super::sort::Category;
+use serde::Serialize;
+use std::{
+    io::{self, Write},
+    str::FromStr,
+};
+
+/// OutputFormat determines how playlist items and the actions taken on a playlist are reported.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OutputFormat {
+    /// Text reports items and actions in human-readable form on standard error.
+    Text,
+    /// Json writes a JSON array of records to standard output.
+    Json,
+    /// Jsonl writes one JSON record per line to standard output.
+    Jsonl,
+}
+
+impl Default for OutputFormat {
+    fn default() -> Self {
+        OutputFormat::Text
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "jsonl" => Ok(OutputFormat::Jsonl),
+            _ => Err(Error::BadInput(format!("invalid output format {}", s))),
+        }
+    }
+}
+
+impl OutputFormat {
+    /// write writes the given records to the given writer, which is normally standard output. Nothing is
+    /// written in text format.
+    pub fn write<T: Serialize>(self: Self, out: &mut dyn Write, records: &[T]) -> Result<()> {
+        match self {
+            OutputFormat::Text => {}
+            OutputFormat::Json => {
+                serde_json::to_writer_pretty(&mut *out, records).map_err(io::Error::from)?;
+                writeln!(out)?;
+            }
+            OutputFormat::Jsonl => {
+                for record in records {
+                    serde_json::to_writer(&mut *out, record).map_err(io::Error::from)?;
+                    writeln!(out)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// ItemRecord is the structured form of a playlist item.
+#[derive(Serialize, Debug)]
+pub struct ItemRecord<'a> {
+    #[serde(flatten)]
+    pub item: &'a Item,
+    pub category: Category,
+}
+
+impl<'a> ItemRecord<'a> {
+    pub fn new(item: &'a Item) -> ItemRecord<'a> {
+        ItemRecord {
+            item: item,
+            category: Category::of(item),
+        }
+    }
+}
+
+/// ActionRecord is the structured form of a change to a playlist. applied is false if the change was only
+/// planned, in dry-run mode.
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct ActionRecord {
+    /// action is one of "add", "archive", "remove", or "move".
+    pub action: &'static str,
+    pub playlist_id: String,
+    pub video_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub playlist_item_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<u32>,
+    pub applied: bool,
+}
+
+impl ActionRecord {
+    /// add records adding the given video to the end of the given playlist.
+    pub fn add(playlist_id: &str, video_id: &str, applied: bool) -> ActionRecord {
+        ActionRecord {
+            action: "add",
+            playlist_id: playlist_id.to_owned(),
+            video_id: video_id.to_owned(),
+            playlist_item_id: None,
+            reason: None,
+            position: None,
+            applied: applied,
+        }
+    }
+
+    /// archive records adding the video of the given item to the given archive playlist at the given position
+    /// or, if there is none, at the end.
+    pub fn archive(
+        archive_to: &str,
+        item: &Item,
+        position: Option<u32>,
+        applied: bool,
+    ) -> ActionRecord {
+        ActionRecord {
+            position: position,
+            ..ActionRecord::add(archive_to, &item.video_id, applied).with_action("archive")
+        }
+    }
+
+    /// remove records removing the given item from the given playlist for the given reason.
+    pub fn remove(playlist_id: &str, item: &Item, reason: &str, applied: bool) -> ActionRecord {
+        ActionRecord {
+            playlist_item_id: Some(item.playlist_item_id.clone()),
+            reason: Some(reason.to_owned()),
+            ..ActionRecord::add(playlist_id, &item.video_id, applied).with_action("remove")
+        }
+    }
+
+    /// moved records the given move in the given playlist.
+    pub fn moved(playlist_id: &str, m: &Move, applied: bool) -> ActionRecord {
+        ActionRecord {
+            playlist_item_id: Some(m.item.playlist_item_id.clone()),
+            position: Some(m.position),
+            ..ActionRecord::add(playlist_id, &m.item.video_id, applied).with_action("move")
+        }
+    }
+
+    fn with_action(self: Self, action: &'static str) -> ActionRecord {
+        ActionRecord {
+            action: action,
+            ..self
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parse_output_formats() {
+        assert_eq!("text".parse::<OutputFormat>().unwrap(), OutputFormat::Text);
+        assert_eq!("json".parse::<OutputFormat>().unwrap(), OutputFormat::Json);
+        assert_eq!(
+            "jsonl".parse::<OutputFormat>().unwrap(),
+            OutputFormat::Jsonl
+        );
+        assert!(matches!(
+            "xml".parse::<OutputFormat>(),
+            Err(Error::BadInput(_))
+        ));
+    }
+
+    #[test]
+    fn item_records_include_the_category() {
+        let mut item = Item::default();
+        item.video_id = "v1".to_string();
+        item.playlist_item_id = "pi1".to_string();
+        item.title = "video 1".to_string();
+        item.blocked = true;
+        assert_eq!(
+            serde_json::to_value(ItemRecord::new(&item)).unwrap(),
+            json!({
+                "video_id": "v1",
+                "playlist_item_id": "pi1",
+                "title": "video 1",
+                "scheduled_start_time": null,
+                "actual_start_time": null,
+                "video_published_at": null,
+                "added_at": null,
+                "blocked": true,
+                "category": "blocked"
+            })
+        );
+    }
+
+    #[test]
+    fn action_records_omit_absent_fields() {
+        let mut item = Item::default();
+        item.video_id = "v1".to_string();
+        item.playlist_item_id = "pi1".to_string();
+        assert_eq!(
+            serde_json::to_value(ActionRecord::remove("pl1", &item, "blocked", false)).unwrap(),
+            json!({
+                "action": "remove",
+                "playlist_id": "pl1",
+                "video_id": "v1",
+                "playlist_item_id": "pi1",
+                "reason": "blocked",
+                "applied": false
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(ActionRecord::archive("pl2", &item, None, true)).unwrap(),
+            json!({
+                "action": "archive",
+                "playlist_id": "pl2",
+                "video_id": "v1",
+                "applied": true
+            })
+        );
+    }
+}
@@ -0,0 +1,284 @@
+use super::error::{Error, Result};
+use super::playlist::Item;
+use super::quota::{Operation, Usage};
+use super::reorder::{self, Move};
+use super::sort::{Category, SortPolicy};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, fmt, fs, path::Path};
+
+/// Plan records the changes needed to sort, and optionally prune, a playlist. A plan can be saved to a file,
+/// reviewed, and applied later provided the playlist has not changed in the meantime.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Plan {
+    /// playlist_id is the id of the playlist the plan applies to.
+    pub playlist_id: String,
+    /// items are the items of the playlist, in playlist order, when the plan was made.
+    pub items: Vec<Item>,
+    /// deletions are the items to be removed from the playlist. These are applied before the moves.
+    pub deletions: Vec<Deletion>,
+    /// moves are the position updates, in the order they must be applied, which sort the remaining items.
+    pub moves: Vec<Move>,
+    /// archive_to is the id of a playlist to which deleted items are added before they are removed.
+    #[serde(default)]
+    pub archive_to: Option<String>,
+    /// sort is the policy the playlist is sorted by. Archived videos are inserted into the archive playlist in
+    /// the same order.
+    #[serde(default)]
+    pub sort: SortPolicy,
+}
+
+/// Deletion removes an item from a playlist.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Deletion {
+    pub item: Item,
+    /// reason is the reason for removing the item, e.g. "surplus".
+    pub reason: String,
+}
+
+impl Deletion {
+    /// archivable returns true if and only if the item can be watched and so is worth archiving. Blocked and
+    /// invalid items are not archived.
+    pub fn archivable(self: &Self) -> bool {
+        matches!(
+            Category::of(&self.item),
+            Category::Viewable | Category::Scheduled
+        )
+    }
+}
+
+impl Plan {
+    /// writes returns the number of YouTube API writes needed to apply the plan. Items which are already in
+    /// the archive playlist are not archived again, so this is an upper bound when there is an archive playlist.
+    pub fn writes(self: &Self) -> usize {
+        self.archived() + self.deletions.len() + self.moves.len()
+    }
+
+    /// quota returns the YouTube API writes needed to apply the plan. Like writes, this is an upper bound when
+    /// there is an archive playlist.
+    pub fn quota(self: &Self) -> Usage {
+        let mut usage = Usage::default();
+        usage.add(Operation::Insert, self.archived() as u64);
+        usage.add(Operation::Delete, self.deletions.len() as u64);
+        usage.add(Operation::Update, self.moves.len() as u64);
+        usage
+    }
+
+    fn archived(self: &Self) -> usize {
+        if self.archive_to.is_some() {
+            self.deletions.iter().filter(|d| d.archivable()).count()
+        } else {
+            0
+        }
+    }
+
+    /// result returns the items of the playlist, in order, after the plan has been applied.
+    pub fn result(self: &Self) -> Vec<Item> {
+        let mut items: Vec<Item> = self
+            .items
+            .iter()
+            .filter(|i| {
+                !self
+                    .deletions
+                    .iter()
+                    .any(|d| d.item.playlist_item_id == i.playlist_item_id)
+            })
+            .cloned()
+            .collect();
+        reorder::apply(&mut items, &self.moves);
+        items
+    }
+
+    /// is_current returns true if and only if the given playlist items, in order, are the items the plan
+    /// was made from.
+    pub fn is_current(self: &Self, items: &[Item]) -> bool {
+        items.len() == self.items.len()
+            && items
+                .iter()
+                .zip(self.items.iter())
+                .all(|(i, j)| i.playlist_item_id == j.playlist_item_id)
+    }
+
+    /// differences describes each way in which the given playlist items, in order, differ from the result of
+    /// applying the plan: items which should have been removed but are still present, items which are
+    /// missing, and items in the wrong position. Items which are not in the plan, e.g. because they were added
+    /// after it was made, are ignored, as are the positions of missing items.
+    pub fn differences(self: &Self, items: &[Item]) -> Vec<String> {
+        let mut differences = vec![];
+        let present: HashSet<&str> = items.iter().map(|i| i.playlist_item_id.as_str()).collect();
+        for d in &self.deletions {
+            if present.contains(d.item.playlist_item_id.as_str()) {
+                differences.push(format!("{} video was not removed: {}", d.reason, d.item));
+            }
+        }
+
+        let expected: Vec<Item> = self
+            .result()
+            .into_iter()
+            .filter(|i| {
+                let present = present.contains(i.playlist_item_id.as_str());
+                if !present {
+                    differences.push(format!("video is missing: {}", i));
+                }
+                present
+            })
+            .collect();
+        let kept: HashSet<&str> = expected
+            .iter()
+            .map(|i| i.playlist_item_id.as_str())
+            .collect();
+        let actual = items
+            .iter()
+            .filter(|i| kept.contains(i.playlist_item_id.as_str()));
+        for (n, (e, a)) in expected.iter().zip(actual).enumerate() {
+            if e.playlist_item_id != a.playlist_item_id {
+                differences.push(format!("position {}: expected {}, found {}", n, e, a));
+            }
+        }
+        differences
+    }
+
+    /// save writes the plan to the given file in JSON format.
+    pub fn save(self: &Self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| Error::BadInput(format!("couldn't serialize plan: {}", e)))?;
+        fs::write(path, json)?;
+        Ok(())
+    }
+
+    /// load reads a plan from the given file, which must have been written by save.
+    pub fn load(path: &Path) -> Result<Plan> {
+        let json = fs::read_to_string(path)?;
+        serde_json::from_str(&json)
+            .map_err(|e| Error::BadInput(format!("invalid plan file {}: {}", path.display(), e)))
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Plan for playlist {}:", self.playlist_id)?;
+        for d in &self.deletions {
+            match &self.archive_to {
+                Some(archive_to) if d.archivable() => writeln!(
+                    f,
+                    "archive to playlist {} and remove {} video: {}",
+                    archive_to, d.reason, d.item
+                )?,
+                _ => writeln!(f, "remove {} video: {}", d.reason, d.item)?,
+            }
+        }
+        for m in &self.moves {
+            writeln!(f, "move to position {}: {}", m.position, m.item)?;
+        }
+        write!(
+            f,
+            "{} API write(s) needed, using {}",
+            self.writes(),
+            self.quota()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::new_items;
+    use super::*;
+
+    #[test]
+    fn result_applies_deletions_then_moves() {
+        let plan = new_plan();
+        assert_eq!(plan.writes(), 2);
+        assert_eq!(plan.quota().units(), 100);
+        assert_eq!(video_ids(&plan.result()), vec!["v3", "v1"]);
+    }
+
+    #[test]
+    fn archiving_adds_writes_for_archivable_items() {
+        let mut plan = new_plan();
+        plan.archive_to = Some("pl2".to_string());
+        assert_eq!(plan.writes(), 2);
+
+        plan.deletions[0].item.video_published_at = Some(chrono::Utc::now());
+        assert_eq!(plan.writes(), 3);
+        assert_eq!(plan.quota().units(), 150);
+    }
+
+    #[test]
+    fn is_current_compares_playlist_items_in_order() {
+        let plan = new_plan();
+        let mut items = plan.items.clone();
+        assert!(plan.is_current(&items));
+
+        items.swap(0, 1);
+        assert!(!plan.is_current(&items));
+
+        items.truncate(2);
+        assert!(!plan.is_current(&items));
+    }
+
+    #[test]
+    fn differences_are_found_after_applying() {
+        let plan = new_plan();
+        assert_eq!(plan.differences(&plan.result()), Vec::<String>::new());
+
+        // Items added after the plan was made are ignored.
+        let mut items = plan.result();
+        items.insert(1, new_items(&[4]).remove(0));
+        assert_eq!(plan.differences(&items), Vec::<String>::new());
+
+        let items = new_items(&[1, 3, 2]);
+        assert_eq!(
+            plan.differences(&items),
+            vec![
+                format!("surplus video was not removed: {}", items[2]),
+                format!("position 0: expected {}, found {}", items[1], items[0]),
+                format!("position 1: expected {}, found {}", items[0], items[1]),
+            ]
+        );
+
+        assert_eq!(
+            plan.differences(&new_items(&[3])),
+            vec![format!("video is missing: {}", new_items(&[1])[0])]
+        );
+    }
+
+    #[test]
+    fn save_and_load() {
+        let plan = new_plan();
+        let path = std::env::temp_dir().join(format!("plan-test-{}.json", std::process::id()));
+        plan.save(&path).unwrap();
+        let loaded = Plan::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), plan);
+    }
+
+    #[test]
+    fn load_rejects_invalid_plan() {
+        let path = std::env::temp_dir().join(format!("bad-plan-test-{}.json", std::process::id()));
+        fs::write(&path, "{}").unwrap();
+        let loaded = Plan::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(loaded, Err(Error::BadInput(_))));
+    }
+
+    fn new_plan() -> Plan {
+        let items = new_items(&[1, 2, 3]);
+        Plan {
+            playlist_id: "pl1".to_string(),
+            deletions: vec![Deletion {
+                item: items[1].clone(),
+                reason: "surplus".to_string(),
+            }],
+            moves: vec![Move {
+                item: items[2].clone(),
+                position: 0,
+            }],
+            items: items,
+            archive_to: None,
+            sort: SortPolicy::default(),
+        }
+    }
+
+    fn video_ids(items: &[Item]) -> Vec<&str> {
+        items.iter().map(|i| i.video_id.as_str()).collect()
+    }
+}
@@ -1,26 +1,96 @@
+use super::backend::{PlaylistBackend, MAX_VIDEO_IDS};
+use super::config::Config;
+use super::error::{Error, Result};
+use super::journal::{Action, Journal, Step};
+use super::output::{ActionRecord, ItemRecord, OutputFormat};
+use super::plan::{Deletion, Plan};
+use super::quota::{Operation, Usage};
+use super::reorder;
+use super::rules::{Rule, RuleSet};
+use super::snapshot::Snapshot;
+use super::sort::{Category, SortPolicy};
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use chrono_tz::Tz;
-use google_youtube3::{
-    api::Scope,
-    api::{PlaylistItem, PlaylistItemListResponse, PlaylistItemSnippet, ResourceId},
-    client::Result,
-    YouTube,
+use google_youtube3::api::PlaylistItem;
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    fmt, fs,
+    io::{self, Write},
+    path::PathBuf,
+    sync::Mutex,
 };
-use hyper::Response;
-use log::debug;
-use std::{cmp::Ordering, fmt};
 
-#[derive(Default, Clone, PartialEq, Debug)]
+#[derive(Default, Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct Item {
     pub video_id: String,
-    playlist_item_id: String,
+    pub playlist_item_id: String,
     pub title: String,
     pub scheduled_start_time: Option<DateTime<Utc>>,
     pub actual_start_time: Option<DateTime<Utc>>,
     pub video_published_at: Option<DateTime<Utc>>,
+    /// added_at is the time the item was added to the playlist.
+    #[serde(default)]
+    pub added_at: Option<DateTime<Utc>>,
     pub blocked: bool,
-    timezone: Option<Tz>,
+    #[serde(skip)]
+    pub(crate) timezone: Option<Tz>,
+}
+
+/// State is the state of the video of a playlist item, as it is reported to the user.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum State {
+    /// Streamed videos have been live streamed and are not blocked.
+    Streamed,
+    /// Uploaded videos have been published without being streamed and are not blocked.
+    Uploaded,
+    /// Scheduled videos have not been streamed yet, but have a scheduled start time.
+    Scheduled,
+    /// Blocked videos are not viewable in the region of the playlist owner.
+    Blocked,
+    /// Invalid videos have no time information, e.g. because they have been deleted.
+    Invalid,
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            State::Streamed => write!(f, "streamed"),
+            State::Uploaded => write!(f, "uploaded"),
+            State::Scheduled => write!(f, "scheduled"),
+            State::Blocked => write!(f, "blocked"),
+            State::Invalid => write!(f, "invalid"),
+        }
+    }
+}
+
+impl Item {
+    /// state returns the state of the video together with the time, if any, at which the video was streamed,
+    /// uploaded, or scheduled. The time of a blocked video is the time it was streamed or uploaded, if it was.
+    /// The time is formatted in the item's timezone.
+    pub fn state(self: &Self) -> (State, Option<String>) {
+        let state = if self.blocked {
+            State::Blocked
+        } else if self.viewable() {
+            if self.scheduled_start_time.is_some() {
+                State::Streamed
+            } else {
+                State::Uploaded
+            }
+        } else if self.scheduled_start_time.is_some() {
+            State::Scheduled
+        } else {
+            State::Invalid
+        };
+        let t = match state {
+            State::Blocked => self.available_time(),
+            State::Scheduled => self.scheduled_start_time,
+            _ => self.viewable_time(),
+        };
+        (state, t.map(|t| format_time(Some(t), self.timezone)))
+    }
 }
 
 pub trait ItemProperties {
@@ -69,32 +139,20 @@ impl ItemProperties for Item {
 }
 
 pub trait Pruning {
-    /// prune returns None if the video should not be pruned. If the video should
+    /// prune returns None if the video should not be pruned by the default pruning rules. If the video should
     /// be pruned, it returns some string which gives the reason for pruning the video.
     fn prune(self: &Self) -> Option<String>;
 }
 
 impl Pruning for Item {
     fn prune(self: &Item) -> Option<String> {
-        if self.blocked {
-            Some("blocked".to_string())
-        } else if self.scheduled_start_time.is_none() && self.video_published_at.is_none() {
-            Some("unscheduled and unpublished or deleted".to_string())
-        } else {
-            None
-        }
+        RuleSet::default().pruner(Utc::now()).reason(self)
     }
 }
 
 impl fmt::Display for Item {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "{}: {} {}",
-            self.video_id,
-            self.title,
-            time(&self, self.timezone)
-        )
+        write!(f, "{}: {} {}", self.video_id, self.title, time(&self))
     }
 }
 
@@ -103,207 +161,874 @@ pub trait Playlist {
     /// items returns a vector of the items in the playlist.
     async fn items(self: &Self) -> Result<Vec<Item>>;
 
-    /// sort orders the playlist as follows:
+    /// sort orders the playlist according to the given sort policy. The default policy orders the playlist
+    /// as follows:
     /// * streamed videos in reverse chronological order (newest first), followed
     /// * not-yet-streamed videos again in reverse chronological order (newest first), followed by
-    /// * videos for which there is no time information.
-    async fn sort(self: &Self) -> Result<()>;
+    /// * videos for which there is no time information, followed by
+    /// * blocked videos.
+    async fn sort(self: &Self, policy: &SortPolicy) -> Result<()>;
 
-    /// prune removes any invalid videos from the playlist. These include:
+    /// prune removes the videos selected by the pruning rules in the given config from the playlist, together
+    /// with any viewable videos beyond the config's max_playable. The default rules remove:
+    /// * blocked videos
     /// * deleted videos
     /// * videos for which there is no time information (e.g. with no live streaming information such as scheduled start time).
-    async fn prune(self: &Self, max_catch_up: usize) -> Result<()>;
+    /// The remaining videos are sorted according to the config's sort policy.
+    async fn prune(self: &Self, config: &Config) -> Result<()>;
+
+    /// plan works out the changes needed to sort the playlist and, if prune is true, to prune it as described
+    /// above, without changing the playlist.
+    async fn plan(self: &Self, config: &Config, prune: bool) -> Result<Plan>;
+
+    /// apply makes the changes in the given plan, or in dry-run mode reports what they would be. An error
+    /// is returned if the playlist has changed since the plan was made.
+    async fn apply(self: &Self, plan: &Plan) -> Result<()>;
+
+    /// restore returns the playlist to the state recorded in the given snapshot. Videos which have been
+    /// removed from the playlist since the snapshot was taken are re-inserted and the items are put back in
+    /// the order of the snapshot. Items added since the snapshot was taken are left at the end of the playlist.
+    async fn restore(self: &Self, snapshot: &Snapshot) -> Result<()>;
+
+    /// reconcile changes the playlist to match the desired state described by the given config. Missing
+    /// pinned videos are added, excluded videos are removed, other videos are pruned as described above, and
+    /// the playlist is sorted with the pinned videos at the top.
+    async fn reconcile(self: &Self, config: &Config) -> Result<()>;
+
+    /// resume finishes applying a plan whose application was interrupted, using the journal of the run. The
+    /// changes which the journal records as made are skipped. An error is returned if the playlist is not in
+    /// the state the journal expects, e.g. because it has been changed since the run was interrupted.
+    async fn resume(self: &Self) -> Result<()>;
 
-    // print prints the playlist to standard error.
+    // print prints the playlist to standard error or, in a structured output format, to standard output.
     async fn print(self: &Self) -> Result<()>;
 }
 
-struct PlaylistImpl {
-    hub: YouTube,
+struct PlaylistImpl<B: PlaylistBackend> {
+    backend: B,
     id: String,
     dry_run: bool,
     debug: bool,
     timezone: Option<Tz>,
+    output: OutputFormat,
+    /// out is where items and changes are written in a structured output format.
+    out: Mutex<Box<dyn Write + Send>>,
+    /// pages caches the pages of playlist items read so far, keyed by page token, so that unchanged pages
+    /// need not be downloaded again.
+    pages: Mutex<HashMap<Option<String>, Page>>,
+    /// journal is the path of the file which records the progress of changes to the playlist, if any.
+    journal: Option<PathBuf>,
 }
 
-/// new constructs a Playlist trait implementation for manipulating the playlist with the given playlist id.
+/// Page is a page of playlist items, as returned by YouTube, together with the ETag of the response it was read
+/// from, if any. The details of the items' videos are not cached, since they change, e.g. when a scheduled
+/// stream starts, without changing the page's ETag.
+#[derive(Clone)]
+struct Page {
+    etag: Option<String>,
+    items: Vec<PlaylistItem>,
+    next_page_token: Option<String>,
+}
+
+/// new constructs a Playlist trait implementation for manipulating the playlist with the given playlist id
+/// using the given backend.
 /// If dry-run is true, information will be printed out but the playlist will not be updated on YouTube.
 /// Debugging information is printed if and only if debug is true.
-pub fn new(hub: YouTube, id: &str, time_zone: String, dry_run: bool, debug: bool) -> impl Playlist {
+/// Playlist items and the changes made to the playlist are reported in the given output format.
+/// If journal is present, the progress of changes is recorded in the given file so that an interrupted run can
+/// be resumed.
+/// An error is returned if the timezone is invalid.
+pub fn new<B: PlaylistBackend>(
+    backend: B,
+    id: &str,
+    time_zone: String,
+    dry_run: bool,
+    debug: bool,
+    output: OutputFormat,
+    journal: Option<PathBuf>,
+) -> Result<impl Playlist> {
+    new_with_out(
+        backend,
+        id,
+        time_zone,
+        dry_run,
+        debug,
+        output,
+        journal,
+        Box::new(io::stdout()),
+    )
+}
+
+/// new_with_out is like new, but writes structured output to the given writer instead of standard output.
+fn new_with_out<B: PlaylistBackend>(
+    backend: B,
+    id: &str,
+    time_zone: String,
+    dry_run: bool,
+    debug: bool,
+    output: OutputFormat,
+    journal: Option<PathBuf>,
+    out: Box<dyn Write + Send>,
+) -> Result<PlaylistImpl<B>> {
     let optional_timezone;
     if time_zone == "" {
         optional_timezone = None;
     } else {
-        optional_timezone = match time_zone.parse() {
-            Ok(v) => Some(v), // there is probably a neater way of doing this
-            Err(e) => {
-                panic!("Invalid timezone: {}", e);
-            }
-        };
+        optional_timezone = Some(
+            time_zone
+                .parse()
+                .map_err(|e| Error::BadInput(format!("invalid timezone: {}", e)))?,
+        );
     }
 
-    PlaylistImpl {
-        hub: hub,
+    Ok(PlaylistImpl {
+        backend: backend,
         id: id.to_owned(),
         dry_run: dry_run,
         debug: debug,
         timezone: optional_timezone,
-    }
+        output: output,
+        out: Mutex::new(out),
+        pages: Mutex::new(HashMap::new()),
+        journal: journal,
+    })
 }
 
 #[async_trait]
-impl Playlist for PlaylistImpl {
-    async fn items(self: &PlaylistImpl) -> Result<Vec<Item>> {
+impl<B: PlaylistBackend> Playlist for PlaylistImpl<B> {
+    async fn items(self: &Self) -> Result<Vec<Item>> {
         let mut list: Vec<Item> = vec![];
+        let mut page_token = None;
+        loop {
+            let page = self.page(&page_token).await?;
+            list.extend(self.details(&page.items).await?);
+            page_token = page.next_page_token;
+            if page_token.is_none() {
+                break;
+            }
+        }
 
-        let (_, mut res) = playlist_items(&self.hub, &self.id, &None).await?;
-        while let Some(items) = &res.items {
-            for item in items {
-                let video_id = item
-                    .content_details
-                    .as_ref()
-                    .unwrap()
-                    .video_id
+        if self.debug {
+            eprintln!("playlist items: {:?}", list);
+        }
+        Ok(list)
+    }
+
+    async fn sort(self: &Self, policy: &SortPolicy) -> Result<()> {
+        let config = Config {
+            sort: policy.clone(),
+            ..Default::default()
+        };
+        let plan = self.plan(&config, false).await?;
+        let mut actions = vec![];
+        let result = self.execute(&plan, &mut actions).await;
+        self.write_actions(&actions, result)
+    }
+
+    async fn prune(self: &Self, config: &Config) -> Result<()> {
+        // Remove surplus and other unwanted videos from the playlist
+        let plan = self.plan(config, true).await?;
+        let mut actions = vec![];
+        let result = self.execute(&plan, &mut actions).await;
+        self.write_actions(&actions, result)
+    }
+
+    async fn plan(self: &Self, config: &Config, prune: bool) -> Result<Plan> {
+        Ok(plan_changes(&self.id, self.items().await?, config, prune))
+    }
+
+    async fn apply(self: &Self, plan: &Plan) -> Result<()> {
+        if plan.playlist_id != self.id {
+            return Err(Error::BadInput(format!(
+                "plan is for playlist {}, not playlist {}",
+                plan.playlist_id, self.id
+            )));
+        }
+        if !plan.is_current(&self.items().await?) {
+            return Err(Error::Conflict(
+                "the playlist has changed since the plan was made".to_string(),
+            ));
+        }
+        let mut actions = vec![];
+        let result = self.execute(plan, &mut actions).await;
+        self.write_actions(&actions, result)
+    }
+
+    async fn restore(self: &Self, snapshot: &Snapshot) -> Result<()> {
+        if snapshot.playlist_id != self.id {
+            return Err(Error::BadInput(format!(
+                "snapshot is of playlist {}, not playlist {}",
+                snapshot.playlist_id, self.id
+            )));
+        }
+        let mut actions = vec![];
+        let result = self.restore_snapshot(snapshot, &mut actions).await;
+        self.write_actions(&actions, result)
+    }
+
+    async fn reconcile(self: &Self, config: &Config) -> Result<()> {
+        let mut actions = vec![];
+        let result = self.reconcile_config(config, &mut actions).await;
+        self.write_actions(&actions, result)
+    }
+
+    async fn resume(self: &Self) -> Result<()> {
+        let path = match &self.journal {
+            Some(path) if path.exists() => path,
+            _ => {
+                return Err(Error::BadInput(
+                    "there is no interrupted run to resume".to_string(),
+                ))
+            }
+        };
+        let mut journal = Journal::load(path)?;
+        if journal.plan.playlist_id != self.id {
+            return Err(Error::BadInput(format!(
+                "the interrupted run was changing playlist {}, not playlist {}",
+                journal.plan.playlist_id, self.id
+            )));
+        }
+
+        // The step which was being made when the run was interrupted may or may not have been made.
+        let next = journal.next();
+        let items = self.items().await?;
+        let made = match journal.steps.get(next).map(|s| &s.action) {
+            Some(Action::Archive {
+                archive_to, item, ..
+            }) => playlist_video_ids(&self.backend, archive_to)
+                .await?
+                .contains(&item.video_id),
+            Some(_) => same_items(&items, &journal.expected(next + 1)),
+            None => false,
+        };
+        if made {
+            journal.steps[next].done = true;
+        } else if !same_items(&items, &journal.expected(next)) {
+            return Err(Error::Conflict(
+                "the playlist has changed since the run was interrupted".to_string(),
+            ));
+        }
+        eprintln!(
+            "Resuming interrupted run: {} of {} change(s) already made",
+            journal.next(),
+            journal.steps.len()
+        );
+
+        let pending: Vec<usize> = (0..journal.steps.len())
+            .filter(|n| !journal.steps[*n].done)
+            .collect();
+        let result = if self.dry_run {
+            for s in journal.steps.iter().filter(|s| !s.done) {
+                self.report(&s.action);
+            }
+            eprintln!("Playlist would be sorted into this order:");
+            print(
+                journal
+                    .plan
+                    .result()
+                    .iter()
+                    .map(|i| self.localise(i))
+                    .collect(),
+            )
+        } else {
+            self.backend.check_quota(&journal.remaining())?;
+            match self.run(&mut journal).await {
+                Ok(()) => self.verify(&journal.plan).await,
+                Err(e) => Err(e),
+            }
+        };
+        let actions: Vec<ActionRecord> = pending
+            .iter()
+            .map(|n| self.record(&journal.steps[*n]))
+            .collect();
+        self.write_actions(&actions, result)
+    }
+
+    async fn print(self: &Self) -> Result<()> {
+        let items = self.items().await?;
+        match self.output {
+            OutputFormat::Text => print(items),
+            format => format.write(
+                &mut *self.out.lock().unwrap(),
+                &items.iter().map(ItemRecord::new).collect::<Vec<_>>(),
+            ),
+        }
+    }
+}
+
+impl<B: PlaylistBackend> PlaylistImpl<B> {
+    /// restore_snapshot restores the playlist as described by restore, adding records of the changes to the
+    /// given records.
+    async fn restore_snapshot(
+        self: &Self,
+        snapshot: &Snapshot,
+        actions: &mut Vec<ActionRecord>,
+    ) -> Result<()> {
+        self.check_not_interrupted()?;
+        let mut items = self.items().await?;
+        let mut matches = match_snapshot(&snapshot.items, &items);
+        let missing: Vec<&Item> = snapshot
+            .items
+            .iter()
+            .zip(&matches)
+            .filter(|(_, m)| m.is_none())
+            .map(|(i, _)| i)
+            .collect();
+        for i in &missing {
+            if self.dry_run {
+                eprintln!(
+                    "Video would be re-inserted into playlist: {}",
+                    self.localise(i)
+                );
+            } else {
+                eprintln!("Re-inserting video into playlist: {}", self.localise(i));
+                self.backend.insert(&self.id, &i.video_id, None).await?;
+            }
+            actions.push(ActionRecord::add(&self.id, &i.video_id, !self.dry_run));
+        }
+        if !self.dry_run && !missing.is_empty() {
+            items = self.items().await?;
+            matches = match_snapshot(&snapshot.items, &items);
+        }
+
+        // Put the items in the snapshot back in order, followed by any items added since the snapshot.
+        let restored: HashSet<usize> = matches.iter().filter_map(|m| *m).collect();
+        let target: Vec<Item> = matches
+            .iter()
+            .filter_map(|m| m.map(|n| items[n].clone()))
+            .chain(
+                items
+                    .iter()
+                    .enumerate()
+                    .filter(|(n, _)| !restored.contains(n))
+                    .map(|(_, i)| i.clone()),
+            )
+            .collect();
+        let plan = Plan {
+            playlist_id: self.id.clone(),
+            moves: reorder::moves(&items, &target),
+            items: items,
+            deletions: vec![],
+            archive_to: None,
+            sort: SortPolicy::default(),
+        };
+        self.execute(&plan, actions).await
+    }
+
+    /// reconcile_config reconciles the playlist with the given config as described by reconcile, adding records
+    /// of the changes to the given records.
+    async fn reconcile_config(
+        self: &Self,
+        config: &Config,
+        actions: &mut Vec<ActionRecord>,
+    ) -> Result<()> {
+        self.check_not_interrupted()?;
+        let mut items = self.items().await?;
+
+        let missing: Vec<&String> = config
+            .pinned
+            .iter()
+            .filter(|id| !items.iter().any(|i| &i.video_id == *id))
+            .collect();
+        for video_id in &missing {
+            if self.dry_run {
+                eprintln!("Pinned video {} would be added to playlist", video_id);
+            } else {
+                eprintln!("Adding pinned video {} to playlist", video_id);
+                self.backend.insert(&self.id, video_id, None).await?;
+            }
+            actions.push(ActionRecord::add(&self.id, video_id, !self.dry_run));
+        }
+        if !self.dry_run && !missing.is_empty() {
+            items = self.items().await?;
+        }
+
+        let plan = plan_changes(&self.id, items, config, true);
+        self.execute(&plan, actions).await
+    }
+
+    /// page returns the page of playlist items with the given page token. If the page has been read before
+    /// and YouTube reports that it has not changed since, the cached page is returned.
+    async fn page(self: &Self, page_token: &Option<String>) -> Result<Page> {
+        let cached = self.pages.lock().unwrap().get(page_token).cloned();
+        let etag = cached.as_ref().and_then(|page| page.etag.as_deref());
+        let res = match self.backend.list_items(&self.id, page_token, etag).await? {
+            Some(res) => res,
+            None => {
+                if self.debug {
+                    eprintln!("page {:?} of playlist items not modified", page_token);
+                }
+                return cached.ok_or_else(|| {
+                    Error::MalformedData(format!(
+                        "page {:?} of playlist {} reported as not modified without an ETag",
+                        page_token, self.id
+                    ))
+                });
+            }
+        };
+
+        let page = Page {
+            etag: res.etag,
+            items: res.items.unwrap_or_default(),
+            next_page_token: res.next_page_token,
+        };
+        if page.etag.is_some() {
+            self.pages
+                .lock()
+                .unwrap()
+                .insert(page_token.clone(), page.clone());
+        }
+        Ok(page)
+    }
+
+    /// details returns the given playlist items together with the current details of their videos, which are
+    /// looked up in batches.
+    async fn details(self: &Self, items: &[PlaylistItem]) -> Result<Vec<Item>> {
+        let video_ids = items
+            .iter()
+            .map(|item| video_id(item))
+            .collect::<Result<Vec<String>>>()?;
+
+        // Look up the videos in batches. Deleted videos have no video record.
+        let mut videos = HashMap::new();
+        for ids in video_ids.chunks(MAX_VIDEO_IDS) {
+            for video in self.backend.videos(ids).await? {
+                if let Some(id) = video.id.clone() {
+                    videos.insert(id, video);
+                }
+            }
+        }
+
+        let mut list = vec![];
+        for (item, video_id) in items.iter().zip(video_ids) {
+            let mut it = Item {
+                video_id: video_id.clone(),
+                playlist_item_id: item.id.clone().ok_or_else(|| malformed(item, "id"))?,
+                title: item
+                    .snippet
                     .as_ref()
-                    .unwrap();
-
-                let (_, v) = self
-                    .hub
-                    .videos()
-                    .list(&vec![
-                        "liveStreamingDetails".into(),
-                        "contentDetails".into(),
-                    ])
-                    .add_id(video_id)
-                    .doit()
-                    .await?;
-
-                let mut it = Item {
-                    video_id: video_id.to_owned(),
-                    playlist_item_id: item.id.as_ref().unwrap().to_owned(),
-                    title: item
-                        .snippet
+                    .and_then(|snippet| snippet.title.clone())
+                    .ok_or_else(|| malformed(item, "title"))?,
+                video_published_at: parse_optional_time(
+                    item.content_details
                         .as_ref()
-                        .unwrap()
-                        .title
+                        .and_then(|details| details.video_published_at.as_ref()),
+                )?,
+                added_at: parse_optional_time(
+                    item.snippet
                         .as_ref()
-                        .unwrap()
-                        .to_owned(),
-                    video_published_at: parse_optional_time(
-                        item.content_details
-                            .as_ref()
-                            .unwrap()
-                            .video_published_at
-                            .as_ref(),
-                    ),
-                    timezone: self.timezone,
-                    ..Default::default()
-                };
-
-                let videos = v.items.unwrap();
-
-                if videos.len() > 0 {
-                    let live_streaming_details =
-                        videos.get(0).unwrap().live_streaming_details.as_ref();
-                    if let Some(details) = live_streaming_details {
-                        it.scheduled_start_time =
-                            parse_optional_time(details.scheduled_start_time.as_ref());
-                        it.actual_start_time =
-                            parse_optional_time(details.actual_start_time.as_ref());
-                    }
-                    if let Some(content_details) = videos.get(0).unwrap().content_details.as_ref() {
-                        if let Some(restriction) = content_details.region_restriction.as_ref() {
-                            if let Some(blocked) = restriction.blocked.as_ref() {
-                                it.blocked = !blocked.is_empty();
-                            }
+                        .and_then(|snippet| snippet.published_at.as_ref()),
+                )?,
+                timezone: self.timezone,
+                ..Default::default()
+            };
+
+            if let Some(video) = videos.get(&video_id) {
+                if let Some(details) = video.live_streaming_details.as_ref() {
+                    it.scheduled_start_time =
+                        parse_optional_time(details.scheduled_start_time.as_ref())?;
+                    it.actual_start_time = parse_optional_time(details.actual_start_time.as_ref())?;
+                }
+                if let Some(content_details) = video.content_details.as_ref() {
+                    if let Some(restriction) = content_details.region_restriction.as_ref() {
+                        if let Some(blocked) = restriction.blocked.as_ref() {
+                            it.blocked = !blocked.is_empty();
                         }
                     }
                 }
-                list.push(it)
-            }
-            if res.next_page_token.is_some() {
-                res = playlist_items(&self.hub, &self.id, &res.next_page_token)
-                    .await?
-                    .1;
-            } else {
-                res.items = None;
             }
+            list.push(it)
         }
+        Ok(list)
+    }
 
-        if self.debug {
-            eprintln!("playlist items: {:?}", list);
+    /// archive_items returns the items of the given archive playlist, in playlist order. Unlike the items of
+    /// the playlist itself, these are not cached.
+    async fn archive_items(self: &Self, archive_to: &str) -> Result<Vec<Item>> {
+        let mut list = vec![];
+        let mut page_token = None;
+        loop {
+            let res = self
+                .backend
+                .list_items(archive_to, &page_token, None)
+                .await?
+                .ok_or_else(|| {
+                    Error::MalformedData(format!(
+                        "page {:?} of playlist {} reported as not modified without an ETag",
+                        page_token, archive_to
+                    ))
+                })?;
+            list.extend(self.details(&res.items.unwrap_or_default()).await?);
+            page_token = res.next_page_token;
+            if page_token.is_none() {
+                return Ok(list);
+            }
         }
-        Ok(list)
     }
 
-    async fn sort(self: &Self) -> Result<()> {
-        let mut items = self.items().await?;
-        let original_items = items.clone();
-        sort_items(&mut items);
-        if items == original_items {
-            eprintln!("Playlist is already in the correct order");
+    /// execute makes the changes in the given plan or, in dry-run mode, reports what they would be. Records of
+    /// the changes are added to the given records, even if an error stops some of them from being made. No
+    /// changes are made if the plan would exceed the backend's quota budget.
+    async fn execute(self: &Self, plan: &Plan, actions: &mut Vec<ActionRecord>) -> Result<()> {
+        self.check_not_interrupted()?;
+        // Videos which are already in the archive playlist are not added again. The others are inserted where
+        // they belong among the archive's items.
+        let mut archive = match &plan.archive_to {
+            Some(archive_to) if plan.deletions.iter().any(|d| d.archivable()) => {
+                self.archive_items(archive_to).await?
+            }
+            _ => vec![],
+        };
+        let archive_items = match &plan.archive_to {
+            Some(_) if plan.deletions.iter().any(|d| d.archivable()) => Some(archive.len()),
+            _ => None,
+        };
+        // Refuse to start making changes which cannot all be made within the quota budget.
+        if !self.dry_run {
+            self.backend.check_quota(&plan.quota())?;
+        }
+        let mut steps = vec![];
+        for d in &plan.deletions {
+            let archive_to = plan.archive_to.as_deref().filter(|_| {
+                d.archivable() && !archive.iter().any(|a| a.video_id == d.item.video_id)
+            });
+            if let Some(archive_to) = archive_to {
+                let position = archive_position(&archive, &d.item, &plan.sort);
+                archive.insert(
+                    position.map_or(archive.len(), |p| p as usize),
+                    d.item.clone(),
+                );
+                steps.push(Action::Archive {
+                    archive_to: archive_to.to_string(),
+                    item: d.item.clone(),
+                    reason: d.reason.clone(),
+                    position: position,
+                });
+            }
+            steps.push(Action::Remove {
+                item: d.item.clone(),
+                reason: d.reason.clone(),
+            });
+        }
+        steps.extend(plan.moves.iter().cloned().map(Action::Move));
+
+        let mut journal = Journal::new(plan, steps);
+        let result = if self.dry_run {
+            journal.steps.iter().for_each(|s| self.report(&s.action));
+            Ok(())
+        } else if journal.steps.is_empty() {
             Ok(())
         } else {
-            if self.dry_run {
-                eprintln!("Playlist would be sorted into this order:");
-                print(items)?;
-                eprintln!("");
-            } else {
-                // Re-order the playlist to match the sorted items.
-                for (n, item) in items.iter().enumerate() {
-                    self.hub
-                        .playlist_items()
-                        .update(PlaylistItem {
-                            id: Some(item.playlist_item_id.clone()),
-                            snippet: Some(PlaylistItemSnippet {
-                                playlist_id: Some(self.id.clone()),
-                                resource_id: Some(ResourceId {
-                                    kind: Some("youtube#video".to_owned()),
-                                    video_id: Some(item.video_id.clone()),
-                                    ..Default::default()
-                                }),
-                                position: Some(n as u32),
-                                ..Default::default()
-                            }),
-                            ..Default::default()
-                        })
-                        .add_scope(Scope::Full)
-                        .doit()
+            match self.run(&mut journal).await {
+                Ok(()) => self.verify(plan).await,
+                Err(e) => Err(e),
+            }
+        };
+        actions.extend(journal.steps.iter().map(|s| self.record(s)));
+        result?;
+
+        if plan.moves.is_empty() {
+            eprintln!("Playlist is already in the correct order");
+        } else if self.dry_run {
+            eprintln!("Playlist would be sorted into this order:");
+            print(plan.result().iter().map(|i| self.localise(i)).collect())?;
+            eprintln!("");
+        }
+
+        if self.dry_run {
+            eprintln!(
+                "Making these changes would require {} API write(s). Together with reading the playlist, they would use {}",
+                journal.steps.len(),
+                run_usage(&journal, archive_items)
+            );
+            if let Err(e) = self.backend.check_quota(&plan.quota()) {
+                eprintln!("Warning: {}", e);
+            }
+        }
+        Ok(())
+    }
+
+    /// record returns a record of the change made, or to be made, by the given step.
+    fn record(self: &Self, step: &Step) -> ActionRecord {
+        match &step.action {
+            Action::Archive {
+                archive_to,
+                item,
+                position,
+                ..
+            } => ActionRecord::archive(archive_to, item, *position, step.done),
+            Action::Remove { item, reason } => {
+                ActionRecord::remove(&self.id, item, reason, step.done)
+            }
+            Action::Move(m) => ActionRecord::moved(&self.id, m, step.done),
+        }
+    }
+
+    /// write_actions writes the given records of changes in the structured output format, if any, and then
+    /// returns the given result. The records are written even if the result is an error, so that changes made
+    /// before the error are still reported.
+    fn write_actions(self: &Self, actions: &[ActionRecord], result: Result<()>) -> Result<()> {
+        let written = self.output.write(&mut *self.out.lock().unwrap(), actions);
+        result.and(written)
+    }
+
+    /// run makes the changes recorded in the given journal which have not already been made, in order. If the
+    /// playlist has a journal file, the journal is saved to it before the first change and after each change,
+    /// and the file is removed once all the changes have been made.
+    async fn run(self: &Self, journal: &mut Journal) -> Result<()> {
+        if let Some(path) = &self.journal {
+            journal.save(path)?;
+        }
+        for n in journal.next()..journal.steps.len() {
+            if journal.steps[n].done {
+                continue;
+            }
+            let action = &journal.steps[n].action;
+            self.report(action);
+            match action {
+                Action::Archive {
+                    archive_to,
+                    item,
+                    position,
+                    ..
+                } => {
+                    self.backend
+                        .insert(archive_to, &item.video_id, *position)
                         .await?;
                 }
+                Action::Remove { item, .. } => self.backend.delete(&item.playlist_item_id).await?,
+                Action::Move(m) => {
+                    self.backend
+                        .update_position(
+                            &self.id,
+                            &m.item.playlist_item_id,
+                            &m.item.video_id,
+                            m.position,
+                        )
+                        .await?
+                }
             }
+            journal.steps[n].done = true;
+            if let Some(path) = &self.journal {
+                journal.save(path)?;
+            }
+        }
+        if let Some(path) = &self.journal {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// verify reads the playlist again and returns an error describing how it differs, if at all, from the
+    /// result of applying the given plan. This catches position updates which YouTube did not apply as
+    /// requested.
+    async fn verify(self: &Self, plan: &Plan) -> Result<()> {
+        let differences = plan.differences(&self.items().await?);
+        if differences.is_empty() {
             Ok(())
+        } else {
+            Err(Error::Verification(format!(
+                "{} difference(s):\n{}",
+                differences.len(),
+                differences.join("\n")
+            )))
         }
     }
 
-    async fn prune(self: &Self, max_streamed: usize) -> Result<()> {
-        // Remove surplus and other unwanted videos from the playlist
-        self.sort().await?;
-        let mut n = 0;
-        for i in self.items().await? {
-            if let Some(prune_reason) = i.prune() {
-                prune_and_log_item(&self.hub, &i, prune_reason, self.dry_run).await?
-            } else if i.viewable() {
-                n += 1;
-                if n > max_streamed {
-                    prune_and_log_item(&self.hub, &i, "surplus".to_string(), self.dry_run).await?
+    /// report describes the given change, which is being made or, in dry-run mode, would be made, on standard
+    /// error. Moves are not described individually, since the order of the playlist is reported instead.
+    fn report(self: &Self, action: &Action) {
+        match action {
+            Action::Archive {
+                archive_to,
+                item,
+                reason,
+                ..
+            } => {
+                if self.dry_run {
+                    eprintln!(
+                        "Video {} would be archived to playlist {}: {}",
+                        reason,
+                        archive_to,
+                        self.localise(item)
+                    );
+                } else {
+                    eprintln!(
+                        "Archiving {} video to playlist {}: {}",
+                        reason,
+                        archive_to,
+                        self.localise(item)
+                    );
+                }
+            }
+            Action::Remove { item, reason } => {
+                if self.dry_run {
+                    eprintln!(
+                        "Video {} would be removed from playlist: {}",
+                        reason,
+                        self.localise(item)
+                    );
+                } else {
+                    eprintln!(
+                        "Removing {} video from playlist: {}",
+                        reason,
+                        self.localise(item)
+                    );
                 }
             }
+            Action::Move(_) => {}
         }
-        Ok(())
     }
 
-    async fn print(self: &Self) -> Result<()> {
-        print(self.items().await?)
+    /// check_not_interrupted returns an error, unless in dry-run mode, if the journal file records an
+    /// interrupted run of this playlist, since making other changes would prevent it from being resumed. An
+    /// error is also returned if the journal file records an interrupted run of another playlist, since the
+    /// file cannot be reused without losing that run's journal.
+    fn check_not_interrupted(self: &Self) -> Result<()> {
+        let path = match &self.journal {
+            Some(path) if !self.dry_run && path.exists() => path,
+            _ => return Ok(()),
+        };
+        let journal = Journal::load(path)?;
+        if journal.plan.playlist_id == self.id {
+            Err(Error::Interrupted(format!(
+                "it did not finish changing the playlist. Finish it with the resume command, or delete {} to abandon it",
+                path.display()
+            )))
+        } else {
+            Err(Error::BadInput(format!(
+                "the journal file {} records an interrupted run of playlist {}: use a different journal file",
+                path.display(),
+                journal.plan.playlist_id
+            )))
+        }
+    }
+
+    /// localise returns a copy of the given item which displays times in the playlist's timezone.
+    fn localise(self: &Self, item: &Item) -> Item {
+        Item {
+            timezone: self.timezone,
+            ..item.clone()
+        }
     }
 }
 
-fn parse_optional_time(t: Option<&String>) -> Option<DateTime<Utc>> {
-    t.as_ref().map(|d: &&String| {
-        DateTime::parse_from_rfc3339(&d)
-            .unwrap()
-            .with_timezone(&Utc)
+/// plan_changes works out the deletions and moves which change the given items of the playlist with the
+/// given id into the desired state described by the given config. Pruning, by the config's rules and
+/// max_playable, only happens if prune is true.
+fn plan_changes(playlist_id: &str, items: Vec<Item>, config: &Config, prune: bool) -> Plan {
+    let mut sorted_items = items.clone();
+    sort_items(&mut sorted_items, &config.sort);
+
+    // Move pinned videos to the top, in the order given.
+    let pinned = |i: &Item| config.pinned.iter().position(|id| id == &i.video_id);
+    sorted_items.sort_by_key(|i| pinned(i).unwrap_or(config.pinned.len()));
+
+    let mut rules = config.rules.clone();
+    if let Some(max) = config.max_playable {
+        rules.rules.push(Rule::MaxPerCategory {
+            category: Category::Viewable,
+            max: max,
+        });
+    }
+    let mut pruner = rules.pruner(Utc::now());
+
+    let mut deletions = vec![];
+    for i in &sorted_items {
+        if pinned(i).is_some() {
+            continue;
+        }
+        if config.excluded.contains(&i.video_id) {
+            deletions.push(new_deletion(i, "excluded".to_string()));
+        } else if !prune {
+            continue;
+        } else if let Some(reason) = pruner.reason(i) {
+            deletions.push(new_deletion(i, reason));
+        }
+    }
+
+    // Only move the remaining items which are out of place.
+    let remaining = |items: &Vec<Item>| -> Vec<Item> {
+        items
+            .iter()
+            .filter(|i| {
+                !deletions
+                    .iter()
+                    .any(|d| d.item.playlist_item_id == i.playlist_item_id)
+            })
+            .cloned()
+            .collect()
+    };
+    let moves = reorder::moves(&remaining(&items), &remaining(&sorted_items));
+
+    Plan {
+        playlist_id: playlist_id.to_owned(),
+        items: items,
+        deletions: deletions,
+        moves: moves,
+        archive_to: config.archive_to.clone(),
+        sort: config.sort.clone(),
+    }
+}
+
+/// match_snapshot returns, for each item in the given snapshot, the index of the corresponding playlist
+/// item, if any. Items are matched by playlist item id or, failing that, by video id.
+fn match_snapshot(snapshot: &[Item], items: &[Item]) -> Vec<Option<usize>> {
+    let mut matched = HashSet::new();
+    let mut matches: Vec<Option<usize>> = snapshot
+        .iter()
+        .map(|s| {
+            let m = items
+                .iter()
+                .position(|i| i.playlist_item_id == s.playlist_item_id);
+            if let Some(n) = m {
+                matched.insert(n);
+            }
+            m
+        })
+        .collect();
+    for (s, m) in snapshot.iter().zip(matches.iter_mut()) {
+        if m.is_none() {
+            *m =
+                (0..items.len()).find(|n| !matched.contains(n) && items[*n].video_id == s.video_id);
+            if let Some(n) = m {
+                matched.insert(*n);
+            }
+        }
+    }
+    matches
+}
+
+fn new_deletion(item: &Item, reason: String) -> Deletion {
+    Deletion {
+        item: item.clone(),
+        reason: reason,
+    }
+}
+
+fn parse_optional_time(t: Option<&String>) -> Result<Option<DateTime<Utc>>> {
+    t.map(|d| {
+        DateTime::parse_from_rfc3339(d)
+            .map(|t| t.with_timezone(&Utc))
+            .map_err(|e| Error::MalformedData(format!("invalid time {}: {}", d, e)))
     })
+    .transpose()
+}
+
+/// video_id returns the id of the video which the given playlist item refers to.
+fn video_id(item: &PlaylistItem) -> Result<String> {
+    item.content_details
+        .as_ref()
+        .and_then(|details| details.video_id.clone())
+        .ok_or_else(|| malformed(item, "video id"))
+}
+
+fn malformed(item: &PlaylistItem, missing: &str) -> Error {
+    Error::MalformedData(format!(
+        "playlist item {} has no {}",
+        item.id.as_deref().unwrap_or("(unknown)"),
+        missing
+    ))
 }
 
 fn print(items: Vec<Item>) -> Result<()> {
@@ -325,152 +1050,125 @@ fn format_time(t: Option<DateTime<Utc>>, tz: Option<Tz>) -> String {
     }
 }
 
-fn time(video: &Item, timezone: Option<Tz>) -> String {
-    if video.viewable() {
-        format!(
-            "{} on {}",
-            if video.scheduled_start_time.is_some() {
-                "streamed"
-            } else {
-                "uploaded"
-            },
-            format_time(video.viewable_time(), timezone)
-        )
-    } else if video.available() {
-        format!(
+/// time describes the state of the given video, and when it reached that state, for listing it.
+fn time(video: &Item) -> String {
+    match video.state() {
+        (State::Streamed, Some(t)) | (State::Uploaded, Some(t)) => {
+            format!("{} on {}", video.state().0, t)
+        }
+        (State::Scheduled, Some(t)) => format!("scheduled for {}", t),
+        (State::Blocked, Some(t)) => format!(
             "{} on {} but **blocked**",
             if video.scheduled_start_time.is_some() {
-                "streamed"
+                State::Streamed
             } else {
-                "uploaded"
+                State::Uploaded
             },
-            format_time(video.available_time(), timezone)
-        )
-    } else if video.scheduled_start_time.is_some() {
-        format!(
-            "scheduled for {}",
-            format_time(video.scheduled_start_time, timezone)
-        )
-    } else {
-        "invalid".to_string()
+            t
+        ),
+        (State::Blocked, None) => "**blocked**".to_string(),
+        _ => "invalid".to_string(),
     }
 }
 
-async fn prune_and_log_item(hub: &YouTube, i: &Item, reason: String, dry_run: bool) -> Result<()> {
-    if !dry_run {
-        eprintln!("Removing {} video from playlist: {}", reason, i);
-        prune_item(&hub, &i.playlist_item_id).await?;
-    } else {
-        eprintln!("Video {} would be removed from playlist: {}", reason, i);
+/// same_items returns true if and only if the given lists contain the same playlist items in the same order.
+fn same_items(items: &[Item], other: &[Item]) -> bool {
+    items.len() == other.len()
+        && items
+            .iter()
+            .zip(other.iter())
+            .all(|(i, j)| i.playlist_item_id == j.playlist_item_id)
+}
+
+/// playlist_video_ids returns the ids of the videos in the playlist with the given id.
+async fn playlist_video_ids<B: PlaylistBackend>(
+    backend: &B,
+    playlist_id: &str,
+) -> Result<HashSet<String>> {
+    let mut ids = HashSet::new();
+    let mut page_token = None;
+    loop {
+        let res = backend
+            .list_items(playlist_id, &page_token, None)
+            .await?
+            .ok_or_else(|| {
+                Error::MalformedData(format!(
+                    "page {:?} of playlist {} reported as not modified without an ETag",
+                    page_token, playlist_id
+                ))
+            })?;
+        for item in res.items.unwrap_or_default() {
+            ids.insert(video_id(&item)?);
+        }
+        page_token = res.next_page_token;
+        if page_token.is_none() {
+            return Ok(ids);
+        }
     }
-    Ok(())
 }
 
-async fn prune_item(hub: &YouTube, playlist_item_id: &String) -> Result<()> {
-    hub.playlist_items()
-        .delete(&playlist_item_id)
-        .add_scope(Scope::Full)
-        .doit()
-        .await?;
-    Ok(())
+/// run_usage returns the calls which a run making the changes in the given journal would use: reading the
+/// playlist, reading the archive playlist if it has the given number of items, making the changes, and reading
+/// the playlist again to verify them.
+fn run_usage(journal: &Journal, archive_items: Option<usize>) -> Usage {
+    let mut usage = journal.remaining();
+    add_reads(&mut usage, journal.plan.items.len());
+    if let Some(n) = archive_items {
+        add_reads(&mut usage, n);
+    }
+    if !journal.steps.is_empty() {
+        add_reads(&mut usage, journal.plan.result().len());
+    }
+    usage
 }
 
-async fn playlist_items(
-    hub: &YouTube,
-    playlist_id: &str,
-    next_page_token: &Option<String>,
-) -> Result<(Response<hyper::body::Body>, PlaylistItemListResponse)> {
-    debug!("listing playlist items");
-    let mut req = hub
-        .playlist_items()
-        .list(&vec![
-            "snippet".into(),
-            "id".into(),
-            "contentDetails".into(),
-        ])
-        .playlist_id(playlist_id);
-    if let Some(next) = next_page_token {
-        req = req.page_token(&next);
-    }
-    req.doit().await
-}
-
-fn sort_items(items: &mut Vec<Item>) {
-    items.sort_by(|v, w| {
-        //println!("v: {:?}\nw: {:?}", v, w);
-        if v.blocked {
-            if w.blocked {
-                // Order blocked items reverse chronologically
-                v.available_time()
-                    .unwrap()
-                    .cmp(&w.available_time().unwrap())
-                    .reverse()
-            } else {
-                // Order unblocked items before blocked items
-                Ordering::Greater
-            }
-        } else if w.blocked {
-            // Order unblocked items before blocked items
-            Ordering::Less
-        } else if v.viewable() {
-            if w.viewable() {
-                // Order viewable items in reverse chronological order
-                v.viewable_time()
-                    .unwrap()
-                    .cmp(&w.viewable_time().unwrap())
-                    .reverse()
-            } else {
-                // Order viewable items before unviewable items
-                Ordering::Less
-            }
-        } else if w.viewable() {
-            // Order viewable items before unviewabled items
-            Ordering::Greater
-        } else if v.scheduled_start_time.is_some() {
-            if w.scheduled_start_time.is_some() {
-                // Order unstreamed, scheduled items in reverse chronological order
-                v.scheduled_start_time
-                    .unwrap()
-                    .cmp(&w.scheduled_start_time.unwrap())
-                    .reverse()
-            } else {
-                // Order unstreamed, scheduled items before unstreamed, unscheduled items
-                Ordering::Less
-            }
-        } else if w.scheduled_start_time.is_some() {
-            // Order unstreamed, scheduled items before unstreamed, unscheduled items
-            Ordering::Greater
-        } else if v.available() {
-            if w.available() {
-                // Order available items in reverse chronological order
-                v.available_time()
-                    .unwrap()
-                    .cmp(&w.available_time().unwrap())
-                    .reverse()
-            } else {
-                // Order unavailable items before available items
-                Ordering::Greater
-            }
-        } else if w.available() {
-            // Order unavailable items before available items
-            Ordering::Less
-        } else {
-            // Leave the order of invalid items alone
-            Ordering::Equal
-        }
-    })
+/// add_reads adds the calls used to read a playlist with the given number of items to the given usage. Each
+/// page of up to MAX_VIDEO_IDS items is listed and then its videos are looked up in a single batch.
+fn add_reads(usage: &mut Usage, items: usize) {
+    let pages = (items + MAX_VIDEO_IDS - 1) / MAX_VIDEO_IDS;
+    usage.add(Operation::ListItems, pages.max(1) as u64);
+    usage.add(Operation::ListVideos, pages as u64);
+}
+
+/// archive_position returns the position at which the given item should be inserted among the given archive
+/// items, which are in playlist order, so that they stay in the order of the given policy. None means the
+/// item belongs at the end.
+fn archive_position(archive: &[Item], item: &Item, policy: &SortPolicy) -> Option<u32> {
+    archive
+        .iter()
+        .position(|a| policy.compare(item, a) == Ordering::Less)
+        .map(|p| p as u32)
+}
+
+fn sort_items(items: &mut Vec<Item>, policy: &SortPolicy) {
+    items.sort_by(|v, w| policy.compare(v, w))
 }
 
 #[cfg(test)]
 mod tests {
+    use super::super::memory::InMemoryBackend;
+    use super::super::quota::{meter, Quota};
+    use super::super::testing::{block_on, new_item, new_playlist, PLAYLIST_ID};
     use super::*;
+    use google_youtube3::api::{
+        Video, VideoContentDetails, VideoContentDetailsRegionRestriction,
+        VideoLiveStreamingDetails, VideoSnippet,
+    };
+    use serde_json::Value;
+    use std::{path::Path, sync::Arc};
+
+    const ARCHIVE_ID: &str = "pl2";
 
     #[test]
     // Since the tests reuse parse_optional_time, we'd better test it!
     fn test_parse_optional_time() {
-        assert_eq!(parse_optional_time(None), None);
+        assert_eq!(parse_optional_time(None).unwrap(), None);
+        assert!(matches!(
+            parse_optional_time(Some(&format!("30 Sep 2021"))),
+            Err(Error::MalformedData(_))
+        ));
 
-        if let Some(t) = parse_optional_time(Some(&format!("2021-09-30T10:56:07+01:00"))) {
+        if let Some(t) = parse_optional_time(Some(&format!("2021-09-30T10:56:07+01:00"))).unwrap() {
             assert_eq!(t.to_rfc3339(), "2021-09-30T09:56:07+00:00");
         } else {
             assert!(false, "failed to produce Some(t)");
@@ -480,7 +1178,7 @@ mod tests {
     #[test]
     fn sort_items_empty() {
         let mut v = vec![];
-        sort_items(&mut v);
+        sort_items(&mut v, &Default::default());
         assert_eq!(v, vec![]);
     }
 
@@ -535,11 +1233,11 @@ mod tests {
     fn sort_reverse_chronologically(f: fn(u32) -> (Item, &'static str)) {
         let message = format!("{} not sorted reverse chronologically", f(0).1);
         let mut v = vec![f(1).0, f(2).0];
-        sort_items(&mut v);
+        sort_items(&mut v, &Default::default());
         assert_video_ids_with_message(v, vec!["v2", "v1"], &message);
 
         v = vec![f(2).0, f(1).0];
-        sort_items(&mut v);
+        sort_items(&mut v, &Default::default());
         assert_video_ids_with_message(v, vec!["v2", "v1"], &message);
     }
 
@@ -559,19 +1257,19 @@ mod tests {
 
         // lower item is less than higher item, regardless of chronological order
         let mut v = vec![higher(2).0, lower(1).0];
-        sort_items(&mut v);
+        sort_items(&mut v, &Default::default());
         assert_video_ids_with_message(v, vec!["v1", "v2"], &message);
 
         v = vec![lower(1).0, higher(2).0];
-        sort_items(&mut v);
+        sort_items(&mut v, &Default::default());
         assert_video_ids_with_message(v, vec!["v1", "v2"], &message);
 
         v = vec![higher(1).0, lower(2).0];
-        sort_items(&mut v);
+        sort_items(&mut v, &Default::default());
         assert_video_ids_with_message(v, vec!["v2", "v1"], &message);
 
         v = vec![lower(2).0, higher(1).0];
-        sort_items(&mut v);
+        sort_items(&mut v, &Default::default());
         assert_video_ids_with_message(v, vec!["v2", "v1"], &message);
     }
 
@@ -579,14 +1277,56 @@ mod tests {
     fn sort_items_invalid() {
         // sort should not change the order of invalid items
         let mut v = vec![new_invalid_item(1).0, new_invalid_item(2).0];
-        sort_items(&mut v);
+        sort_items(&mut v, &Default::default());
         assert_video_ids(v, vec!["v1", "v2"]);
 
         v = vec![new_invalid_item(2).0, new_invalid_item(1).0];
-        sort_items(&mut v);
+        sort_items(&mut v, &Default::default());
         assert_video_ids(v, vec!["v2", "v1"]);
     }
 
+    #[test]
+    fn item_state() {
+        let utc = |i: Item| Item {
+            timezone: Some(Tz::UTC),
+            ..i
+        };
+        let (i, _) = new_streamed_item(1);
+        let t = format_time(i.actual_start_time, Some(Tz::UTC));
+        assert_eq!(utc(i).state(), (State::Streamed, Some(t)));
+
+        let (i, _) = new_uploaded_item(1);
+        let t = format_time(i.video_published_at, Some(Tz::UTC));
+        assert_eq!(utc(i).state(), (State::Uploaded, Some(t)));
+
+        let (i, _) = new_scheduled_item(1);
+        let t = format_time(i.scheduled_start_time, Some(Tz::UTC));
+        assert_eq!(utc(i).state(), (State::Scheduled, Some(t)));
+
+        assert_eq!(new_blocked_item(1).0.state().0, State::Blocked);
+        assert_eq!(new_invalid_item(1).0.state(), (State::Invalid, None));
+    }
+
+    #[test]
+    fn listing_agrees_with_state() {
+        let (i, _) = new_streamed_item(1);
+        let blocked = Item {
+            blocked: true,
+            timezone: Some(Tz::UTC),
+            ..i.clone()
+        };
+        let t = format_time(i.actual_start_time, Some(Tz::UTC));
+        assert_eq!(time(&blocked), format!("streamed on {} but **blocked**", t));
+
+        let scheduled_but_blocked = Item {
+            blocked: true,
+            ..new_scheduled_item(1).0
+        };
+        assert_eq!(scheduled_but_blocked.state(), (State::Blocked, None));
+        assert_eq!(time(&scheduled_but_blocked), "**blocked**");
+        assert_eq!(time(&new_invalid_item(1).0), "invalid");
+    }
+
     #[test]
     fn prune_item() {
         assert!(new_scheduled_item(1).0.prune().is_none());
@@ -598,16 +1338,723 @@ mod tests {
         assert!(new_deleted_item(1).0.prune().is_some());
     }
 
+    #[test]
+    fn items_are_read_from_backend() {
+        // more items than fit on one page
+        let (backend, expected) = backend_with(vec![
+            new_streamed_item(1).0,
+            new_uploaded_item(2).0,
+            new_scheduled_item(3).0,
+            new_blocked_item(4).0,
+            new_deleted_item(5).0,
+            new_streamed_item(6).0,
+            new_uploaded_item(7).0,
+        ]);
+        let items = block_on(new_playlist(backend.with_page_size(5), true).items()).unwrap();
+        assert_eq!(items, expected);
+    }
+
+    #[test]
+    fn items_look_up_videos_in_batches() {
+        let backend = InMemoryBackend::new();
+        for n in 0..MAX_VIDEO_IDS + 15 {
+            backend.add_item(PLAYLIST_ID, &format!("v{}", n), "video", None);
+        }
+        let mut video = new_video(&new_streamed_item(1).0);
+        video.id = Some(format!("v{}", MAX_VIDEO_IDS));
+        backend.add_video(video);
+
+        let items = block_on(new_playlist(backend.clone(), true).items()).unwrap();
+
+        // a page holds a full batch, so there is one lookup for each page
+        assert_eq!(backend.pages_listed(), 2);
+        assert_eq!(backend.video_lookups(), 2);
+        assert_eq!(items.len(), MAX_VIDEO_IDS + 15);
+        for (n, item) in items.iter().enumerate() {
+            assert_eq!(item.video_id, format!("v{}", n));
+            assert_eq!(item.viewable(), n == MAX_VIDEO_IDS);
+        }
+    }
+
+    #[test]
+    fn unchanged_pages_are_not_read_again() {
+        let (backend, expected) = backend_with(vec![
+            new_streamed_item(1).0,
+            new_streamed_item(2).0,
+            new_streamed_item(3).0,
+            new_streamed_item(4).0,
+            new_streamed_item(5).0,
+            new_streamed_item(6).0,
+        ]);
+        let backend = backend.with_page_size(5);
+        let playlist = new_playlist(backend.clone(), false);
+        assert_eq!(block_on(playlist.items()).unwrap(), expected);
+        assert_eq!(backend.pages_listed(), 2);
+
+        // the cached pages are reused
+        assert_eq!(block_on(playlist.items()).unwrap(), expected);
+        assert_eq!(backend.pages_listed(), 2);
+
+        // sorting changes the playlist, so it is read again
+        block_on(playlist.sort(&Default::default())).unwrap();
+        assert_eq!(
+            video_ids(&block_on(playlist.items()).unwrap()),
+            vec!["v6", "v5", "v4", "v3", "v2", "v1"]
+        );
+        assert_eq!(backend.pages_listed(), 4);
+    }
+
+    #[test]
+    fn videos_of_unchanged_pages_are_looked_up_again() {
+        let (backend, mut expected) = backend_with(vec![new_scheduled_item(1).0]);
+        let playlist = new_playlist(backend.clone(), true);
+        assert_eq!(block_on(playlist.items()).unwrap(), expected);
+
+        // the stream starts, which does not change the playlist
+        expected[0].actual_start_time = new_streamed_item(1).0.actual_start_time;
+        backend.add_video(new_video(&expected[0]));
+        assert_eq!(block_on(playlist.items()).unwrap(), expected);
+        assert_eq!(backend.pages_listed(), 1);
+        assert_eq!(backend.video_lookups(), 2);
+    }
+
+    #[test]
+    fn sort_updates_playlist_order() {
+        let (backend, _) = backend_with(vec![
+            new_scheduled_item(1).0,
+            new_invalid_item(2).0,
+            new_streamed_item(3).0,
+            new_uploaded_item(4).0,
+            new_blocked_item(5).0,
+        ]);
+        block_on(new_playlist(backend.clone(), false).sort(&Default::default())).unwrap();
+        assert_eq!(
+            backend.video_ids(PLAYLIST_ID),
+            vec!["v4", "v3", "v1", "v2", "v5"]
+        );
+    }
+
+    #[test]
+    fn sort_moves_only_items_which_are_out_of_place() {
+        let (backend, _) = backend_with(vec![
+            new_streamed_item(9).0,
+            new_streamed_item(7).0,
+            new_streamed_item(6).0,
+            new_streamed_item(8).0,
+            new_streamed_item(5).0,
+        ]);
+        block_on(new_playlist(backend.clone(), false).sort(&Default::default())).unwrap();
+        assert_eq!(
+            backend.video_ids(PLAYLIST_ID),
+            vec!["v9", "v8", "v7", "v6", "v5"]
+        );
+        assert_eq!(backend.position_updates(), 1);
+    }
+
+    #[test]
+    fn sort_over_quota_budget_makes_no_changes() {
+        let (backend, _) = backend_with(vec![
+            new_streamed_item(1).0,
+            new_streamed_item(2).0,
+            new_streamed_item(3).0,
+        ]);
+        let quota = Quota::new(Some(100));
+        let playlist = new_playlist(meter(backend.clone(), quota.clone()), false);
+        assert!(matches!(
+            block_on(playlist.sort(&Default::default())),
+            Err(Error::Quota(_))
+        ));
+        assert_eq!(backend.video_ids(PLAYLIST_ID), vec!["v1", "v2", "v3"]);
+        assert_eq!(backend.position_updates(), 0);
+        assert_eq!(quota.used().units(), 2);
+    }
+
+    #[test]
+    fn run_usage_matches_the_quota_used() {
+        let backend = InMemoryBackend::new();
+        for n in 0..MAX_VIDEO_IDS + 5 {
+            backend.add_item(
+                PLAYLIST_ID,
+                &format!("v{}", n),
+                "video",
+                Some(format!("2021-09-01T{:02}:{:02}:00Z", n / 60, n % 60)),
+            );
+        }
+        let config = max_playable(MAX_VIDEO_IDS);
+        let plan = block_on(new_playlist(backend.clone(), true).plan(&config, true)).unwrap();
+        let mut steps: Vec<Action> = plan
+            .deletions
+            .iter()
+            .map(|d| Action::Remove {
+                item: d.item.clone(),
+                reason: d.reason.clone(),
+            })
+            .collect();
+        steps.extend(plan.moves.iter().cloned().map(Action::Move));
+        let estimate = run_usage(&Journal::new(&plan, steps), None);
+
+        let quota = Quota::new(None);
+        let playlist = new_playlist(meter(backend.clone(), quota.clone()), false);
+        block_on(playlist.prune(&config)).unwrap();
+        assert_eq!(quota.used(), estimate);
+        assert_eq!(backend.video_ids(PLAYLIST_ID).len(), MAX_VIDEO_IDS);
+    }
+
+    #[test]
+    fn sort_verifies_the_playlist_order() {
+        let (backend, _) = backend_with(vec![
+            new_streamed_item(1).0,
+            new_streamed_item(2).0,
+            new_streamed_item(3).0,
+        ]);
+        backend.ignore_moves();
+        assert!(matches!(
+            block_on(new_playlist(backend.clone(), false).sort(&Default::default())),
+            Err(Error::Verification(_))
+        ));
+    }
+
+    #[test]
+    fn sort_dry_run_leaves_playlist_alone() {
+        let (backend, _) = backend_with(vec![new_scheduled_item(1).0, new_streamed_item(2).0]);
+        block_on(new_playlist(backend.clone(), true).sort(&Default::default())).unwrap();
+        assert_eq!(backend.video_ids(PLAYLIST_ID), vec!["v1", "v2"]);
+    }
+
+    #[test]
+    fn prune_removes_invalid_and_surplus_items() {
+        let (backend, _) = backend_with(vec![
+            new_streamed_item(1).0,
+            new_streamed_item(2).0,
+            new_streamed_item(3).0,
+            new_blocked_item(4).0,
+            new_deleted_item(5).0,
+            new_scheduled_item(6).0,
+        ]);
+        block_on(new_playlist(backend.clone(), false).prune(&max_playable(2))).unwrap();
+        assert_eq!(backend.video_ids(PLAYLIST_ID), vec!["v3", "v2", "v6"]);
+    }
+
+    #[test]
+    fn prune_dry_run_leaves_playlist_alone() {
+        let (backend, _) = backend_with(vec![new_streamed_item(1).0, new_blocked_item(2).0]);
+        block_on(new_playlist(backend.clone(), true).prune(&max_playable(0))).unwrap();
+        assert_eq!(backend.video_ids(PLAYLIST_ID), vec!["v1", "v2"]);
+    }
+
+    #[test]
+    fn prune_archives_watchable_videos_before_removing_them() {
+        let (backend, _) = backend_with(vec![
+            new_streamed_item(1).0,
+            new_streamed_item(2).0,
+            new_streamed_item(3).0,
+            new_blocked_item(4).0,
+        ]);
+        backend.add_item(ARCHIVE_ID, "v1", "video 1", None);
+        let config = Config {
+            max_playable: Some(1),
+            archive_to: Some(ARCHIVE_ID.to_string()),
+            ..Default::default()
+        };
+
+        block_on(new_playlist(backend.clone(), true).prune(&config)).unwrap();
+        assert_eq!(backend.video_ids(ARCHIVE_ID), vec!["v1"]);
+
+        block_on(new_playlist(backend.clone(), false).prune(&config)).unwrap();
+        assert_eq!(backend.video_ids(PLAYLIST_ID), vec!["v3"]);
+        // v1 is already archived, v2 is newer so it is inserted before v1, and blocked videos are not archived
+        assert_eq!(backend.video_ids(ARCHIVE_ID), vec!["v2", "v1"]);
+    }
+
+    #[test]
+    fn archive_position_follows_the_sort_order() {
+        let archive = vec![new_streamed_item(4).0, new_streamed_item(2).0];
+        let policy = SortPolicy::default();
+        assert_eq!(
+            archive_position(&archive, &new_streamed_item(5).0, &policy),
+            Some(0)
+        );
+        assert_eq!(
+            archive_position(&archive, &new_streamed_item(3).0, &policy),
+            Some(1)
+        );
+        assert_eq!(
+            archive_position(&archive, &new_streamed_item(1).0, &policy),
+            None
+        );
+    }
+
+    #[test]
+    fn plan_records_deletions_with_reasons_and_moves() {
+        let (backend, _) = backend_with(vec![
+            new_streamed_item(1).0,
+            new_streamed_item(2).0,
+            new_blocked_item(3).0,
+            new_deleted_item(4).0,
+            new_streamed_item(5).0,
+        ]);
+        let plan =
+            block_on(new_playlist(backend.clone(), false).plan(&max_playable(1), true)).unwrap();
+
+        let deletions: Vec<(&str, &str)> = plan
+            .deletions
+            .iter()
+            .map(|d| (d.item.video_id.as_str(), d.reason.as_str()))
+            .collect();
+        assert_eq!(
+            deletions,
+            vec![
+                ("v2", "surplus (more than 1 viewable)"),
+                ("v1", "surplus (more than 1 viewable)"),
+                ("v4", "unscheduled and unpublished or deleted"),
+                ("v3", "blocked")
+            ]
+        );
+        assert_eq!(plan.moves, vec![]);
+        assert_eq!(
+            backend.video_ids(PLAYLIST_ID),
+            vec!["v1", "v2", "v3", "v4", "v5"]
+        );
+    }
+
+    #[test]
+    fn apply_makes_planned_changes() {
+        let (backend, _) = backend_with(vec![
+            new_streamed_item(1).0,
+            new_blocked_item(2).0,
+            new_streamed_item(3).0,
+            new_scheduled_item(4).0,
+        ]);
+        let plan =
+            block_on(new_playlist(backend.clone(), true).plan(&max_playable(6), true)).unwrap();
+        assert_eq!(plan.writes(), 2);
+
+        block_on(new_playlist(backend.clone(), false).apply(&plan)).unwrap();
+        assert_eq!(backend.video_ids(PLAYLIST_ID), vec!["v3", "v1", "v4"]);
+    }
+
+    #[test]
+    fn apply_refuses_outdated_plan() {
+        let (backend, _) = backend_with(vec![new_streamed_item(1).0, new_streamed_item(2).0]);
+        let plan =
+            block_on(new_playlist(backend.clone(), true).plan(&Default::default(), false)).unwrap();
+        backend.add_item(PLAYLIST_ID, "v3", "video 3", None);
+
+        assert!(matches!(
+            block_on(new_playlist(backend.clone(), false).apply(&plan)),
+            Err(Error::Conflict(_))
+        ));
+        assert_eq!(backend.video_ids(PLAYLIST_ID), vec!["v1", "v2", "v3"]);
+    }
+
+    #[test]
+    fn restore_reinserts_removed_items_and_restores_order() {
+        let (backend, _) = backend_with(vec![
+            new_streamed_item(1).0,
+            new_streamed_item(2).0,
+            new_streamed_item(3).0,
+            new_scheduled_item(4).0,
+        ]);
+        let playlist = new_playlist(backend.clone(), false);
+        let snapshot = Snapshot::new(PLAYLIST_ID, block_on(playlist.items()).unwrap());
+
+        block_on(playlist.prune(&max_playable(1))).unwrap();
+        backend.add_item(PLAYLIST_ID, "v5", "video 5", None);
+        assert_eq!(backend.video_ids(PLAYLIST_ID), vec!["v3", "v4", "v5"]);
+
+        block_on(playlist.restore(&snapshot)).unwrap();
+        assert_eq!(
+            backend.video_ids(PLAYLIST_ID),
+            vec!["v1", "v2", "v3", "v4", "v5"]
+        );
+        let items = block_on(playlist.items()).unwrap();
+        assert_eq!(
+            items[0].actual_start_time,
+            snapshot.items[0].actual_start_time
+        );
+    }
+
+    #[test]
+    fn restore_dry_run_leaves_playlist_alone() {
+        let (backend, _) = backend_with(vec![new_streamed_item(1).0, new_streamed_item(2).0]);
+        let snapshot = Snapshot::new(
+            PLAYLIST_ID,
+            block_on(new_playlist(backend.clone(), true).items()).unwrap(),
+        );
+        block_on(new_playlist(backend.clone(), false).sort(&Default::default())).unwrap();
+
+        block_on(new_playlist(backend.clone(), true).restore(&snapshot)).unwrap();
+        assert_eq!(backend.video_ids(PLAYLIST_ID), vec!["v2", "v1"]);
+    }
+
+    #[test]
+    fn match_snapshot_by_playlist_item_then_video() {
+        let (_, items) = backend_with(vec![
+            new_streamed_item(1).0,
+            new_streamed_item(2).0,
+            new_streamed_item(1).0,
+        ]);
+        let mut snapshot = items.clone();
+        snapshot[0].playlist_item_id = "removed".to_string();
+        snapshot.push(new_streamed_item(3).0);
+
+        assert_eq!(
+            match_snapshot(&snapshot, &items),
+            vec![Some(0), Some(1), Some(2), None]
+        );
+        assert_eq!(
+            match_snapshot(&snapshot, &items[1..]),
+            vec![None, Some(0), Some(1), None]
+        );
+    }
+
+    #[test]
+    fn reconcile_adds_pinned_and_removes_excluded_videos() {
+        let (backend, _) = backend_with(vec![
+            new_streamed_item(1).0,
+            new_streamed_item(2).0,
+            new_streamed_item(3).0,
+            new_blocked_item(4).0,
+            new_streamed_item(5).0,
+        ]);
+        backend.add_video(new_video(&new_uploaded_item(6).0));
+        let config = Config {
+            pinned: vec!["v6".to_string(), "v1".to_string()],
+            excluded: vec!["v5".to_string()],
+            max_playable: Some(1),
+            ..Default::default()
+        };
+
+        block_on(new_playlist(backend.clone(), true).reconcile(&config)).unwrap();
+        assert_eq!(
+            backend.video_ids(PLAYLIST_ID),
+            vec!["v1", "v2", "v3", "v4", "v5"]
+        );
+
+        block_on(new_playlist(backend.clone(), false).reconcile(&config)).unwrap();
+        assert_eq!(backend.video_ids(PLAYLIST_ID), vec!["v6", "v1", "v3"]);
+    }
+
+    #[test]
+    fn plan_changes_with_empty_config_only_sorts() {
+        let (_, items) = backend_with(vec![new_streamed_item(1).0, new_streamed_item(2).0]);
+        let plan = plan_changes(PLAYLIST_ID, items, &Default::default(), false);
+        assert_eq!(plan.deletions, vec![]);
+        assert_eq!(video_ids(&plan.result()), vec!["v2", "v1"]);
+    }
+
+    #[test]
+    fn structured_output_does_not_change_behaviour() {
+        for format in vec![OutputFormat::Json, OutputFormat::Jsonl] {
+            let (backend, _) = backend_with(vec![new_streamed_item(1).0, new_streamed_item(2).0]);
+            let (playlist, out) = new_playlist_with_output(backend.clone(), format);
+            block_on(playlist.print()).unwrap();
+            assert!(out.take().contains("\"video_id\""));
+            block_on(playlist.sort(&Default::default())).unwrap();
+            assert_eq!(backend.video_ids(PLAYLIST_ID), vec!["v2", "v1"]);
+            let records = out.take();
+            let actions = match format {
+                OutputFormat::Json => serde_json::from_str::<Vec<Value>>(&records).unwrap(),
+                _ => records
+                    .lines()
+                    .map(|l| serde_json::from_str(l).unwrap())
+                    .collect(),
+            };
+            assert_eq!(actions.len(), 1);
+            assert_eq!(actions[0]["action"], "move");
+            assert_eq!(actions[0]["video_id"], "v1");
+            assert_eq!(actions[0]["position"], 1);
+            assert_eq!(actions[0]["applied"], true);
+        }
+    }
+
+    #[test]
+    fn changes_made_before_an_error_are_reported() {
+        let (backend, _) = backend_with(vec![
+            new_streamed_item(1).0,
+            new_streamed_item(2).0,
+            new_streamed_item(3).0,
+        ]);
+        backend.ignore_moves();
+        let (playlist, out) = new_playlist_with_output(backend, OutputFormat::Jsonl);
+        assert!(matches!(
+            block_on(playlist.sort(&Default::default())),
+            Err(Error::Verification(_))
+        ));
+        let actions: Vec<Value> = out
+            .take()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert!(!actions.is_empty());
+        assert!(actions
+            .iter()
+            .all(|a| a["action"] == "move" && a["applied"] == true));
+    }
+
+    #[test]
+    fn new_rejects_invalid_timezone() {
+        assert!(new(
+            InMemoryBackend::new(),
+            PLAYLIST_ID,
+            "UTC".to_string(),
+            true,
+            false,
+            OutputFormat::Text,
+            None
+        )
+        .is_ok());
+        assert!(matches!(
+            new(
+                InMemoryBackend::new(),
+                PLAYLIST_ID,
+                "Nowhere/Special".to_string(),
+                true,
+                false,
+                OutputFormat::Text,
+                None
+            ),
+            Err(Error::BadInput(_))
+        ));
+    }
+
+    #[test]
+    fn interrupted_changes_are_resumed() {
+        let (backend, _) = backend_with((1..=4).map(|n| new_streamed_item(n).0).collect());
+        let path = journal_path("resume");
+        let playlist = new_journaled_playlist(backend.clone(), &path, false);
+        let plan = block_on(playlist.plan(&Default::default(), false)).unwrap();
+        assert_eq!(plan.moves.len(), 3);
+
+        // Simulate a run which was interrupted after making the second move but before recording it.
+        let mut journal = Journal::new(
+            &plan,
+            plan.moves.iter().cloned().map(Action::Move).collect(),
+        );
+        journal.steps[0].done = true;
+        journal.save(&path).unwrap();
+        for m in &plan.moves[..2] {
+            block_on(backend.update_position(
+                PLAYLIST_ID,
+                &m.item.playlist_item_id,
+                &m.item.video_id,
+                m.position,
+            ))
+            .unwrap();
+        }
+
+        block_on(playlist.resume()).unwrap();
+        assert_eq!(backend.video_ids(PLAYLIST_ID), vec!["v4", "v3", "v2", "v1"]);
+        assert_eq!(backend.position_updates(), 3);
+        assert!(!path.exists());
+        assert!(matches!(
+            block_on(playlist.resume()),
+            Err(Error::BadInput(_))
+        ));
+    }
+
+    #[test]
+    fn resume_refuses_to_change_a_changed_playlist() {
+        let (backend, _) = backend_with(vec![new_streamed_item(1).0, new_streamed_item(2).0]);
+        let path = journal_path("changed");
+        let playlist = new_journaled_playlist(backend.clone(), &path, false);
+        let plan = block_on(playlist.plan(&Default::default(), false)).unwrap();
+        Journal::new(
+            &plan,
+            plan.moves.iter().cloned().map(Action::Move).collect(),
+        )
+        .save(&path)
+        .unwrap();
+        backend.add_item(PLAYLIST_ID, "v3", "video 3", None);
+
+        let result = block_on(playlist.resume());
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(Error::Conflict(_))));
+        assert_eq!(backend.position_updates(), 0);
+    }
+
+    #[test]
+    fn changes_wait_for_an_interrupted_run_to_be_resumed() {
+        let (backend, _) = backend_with(vec![new_streamed_item(1).0, new_streamed_item(2).0]);
+        let path = journal_path("wait");
+        let plan =
+            block_on(new_playlist(backend.clone(), true).plan(&Default::default(), false)).unwrap();
+        Journal::new(&plan, vec![]).save(&path).unwrap();
+
+        let dry_run = new_journaled_playlist(backend.clone(), &path, true);
+        assert!(block_on(dry_run.sort(&Default::default())).is_ok());
+        let playlist = new_journaled_playlist(backend.clone(), &path, false);
+        assert!(matches!(
+            block_on(playlist.sort(&Default::default())),
+            Err(Error::Interrupted(_))
+        ));
+        assert_eq!(backend.video_ids(PLAYLIST_ID), vec!["v1", "v2"]);
+
+        // the journal of another playlist's run is not overwritten
+        backend.add_item("pl3", "v1", "video 1", None);
+        let other = new(
+            backend.clone(),
+            "pl3",
+            "".to_string(),
+            false,
+            false,
+            OutputFormat::Text,
+            Some(path.clone()),
+        )
+        .unwrap();
+        assert!(matches!(
+            block_on(other.prune(&max_playable(0))),
+            Err(Error::BadInput(_))
+        ));
+        assert_eq!(backend.video_ids("pl3"), vec!["v1"]);
+
+        fs::remove_file(&path).unwrap();
+        block_on(playlist.sort(&Default::default())).unwrap();
+        assert_eq!(backend.video_ids(PLAYLIST_ID), vec!["v2", "v1"]);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn items_reports_malformed_playlist_items() {
+        let backend = InMemoryBackend::new();
+        backend.add_item(PLAYLIST_ID, "v1", "video 1", Some("yesterday".to_string()));
+        assert!(matches!(
+            block_on(new_playlist(backend, true).items()),
+            Err(Error::MalformedData(_))
+        ));
+    }
+
+    fn new_playlist_with_output(
+        backend: InMemoryBackend,
+        output: OutputFormat,
+    ) -> (impl Playlist, SharedBuffer) {
+        let out = SharedBuffer::default();
+        let playlist = new_with_out(
+            backend,
+            PLAYLIST_ID,
+            "".to_string(),
+            false,
+            false,
+            output,
+            None,
+            Box::new(out.clone()),
+        )
+        .unwrap();
+        (playlist, out)
+    }
+
+    /// SharedBuffer collects structured output so that tests can inspect it.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl SharedBuffer {
+        /// take returns the output written so far and clears the buffer.
+        fn take(self: &Self) -> String {
+            String::from_utf8(std::mem::take(&mut *self.0.lock().unwrap())).unwrap()
+        }
+    }
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn new_journaled_playlist(
+        backend: InMemoryBackend,
+        journal: &Path,
+        dry_run: bool,
+    ) -> impl Playlist {
+        new(
+            backend,
+            PLAYLIST_ID,
+            "".to_string(),
+            dry_run,
+            false,
+            OutputFormat::Text,
+            Some(journal.to_owned()),
+        )
+        .unwrap()
+    }
+
+    fn journal_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("journal-{}-test-{}.json", name, std::process::id()))
+    }
+
+    /// backend_with returns an in-memory backend containing a playlist with the given items, together with
+    /// the items as they should be read back from the backend.
+    fn backend_with(items: Vec<Item>) -> (InMemoryBackend, Vec<Item>) {
+        let backend = InMemoryBackend::new();
+        let items = items
+            .into_iter()
+            .map(|i| {
+                let playlist_item_id = backend.add_item(
+                    PLAYLIST_ID,
+                    &i.video_id,
+                    &i.title,
+                    i.video_published_at.map(|t| t.to_rfc3339()),
+                );
+                if i.available() || i.scheduled_start_time.is_some() {
+                    // deleted videos have no video details
+                    backend.add_video(new_video(&i));
+                }
+                Item {
+                    playlist_item_id: playlist_item_id,
+                    ..i
+                }
+            })
+            .collect();
+        (backend, items)
+    }
+
+    fn new_video(i: &Item) -> Video {
+        Video {
+            id: Some(i.video_id.clone()),
+            snippet: Some(VideoSnippet {
+                title: Some(i.title.clone()),
+                published_at: i.video_published_at.map(|t| t.to_rfc3339()),
+                ..Default::default()
+            }),
+            live_streaming_details: if i.scheduled_start_time.is_some() {
+                Some(VideoLiveStreamingDetails {
+                    scheduled_start_time: i.scheduled_start_time.map(|t| t.to_rfc3339()),
+                    actual_start_time: i.actual_start_time.map(|t| t.to_rfc3339()),
+                    ..Default::default()
+                })
+            } else {
+                None
+            },
+            content_details: Some(VideoContentDetails {
+                region_restriction: if i.blocked {
+                    Some(VideoContentDetailsRegionRestriction {
+                        blocked: Some(vec!["GB".to_string()]),
+                        ..Default::default()
+                    })
+                } else {
+                    None
+                },
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
     fn new_scheduled_item(n: u32) -> (Item, &'static str) {
         let mut i = new_item(n);
         i.scheduled_start_time =
-            parse_optional_time(Some(&format!("2021-09-30T10:55:0{}+01:00", n)));
+            parse_optional_time(Some(&format!("2021-09-30T10:55:0{}+01:00", n))).unwrap();
         (i, "scheduled item")
     }
 
     fn new_streamed_item(n: u32) -> (Item, &'static str) {
         let (mut i, _) = new_scheduled_item(n);
-        i.actual_start_time = parse_optional_time(Some(&format!("2021-09-30T10:56:0{}+01:00", n)));
+        i.actual_start_time =
+            parse_optional_time(Some(&format!("2021-09-30T10:56:0{}+01:00", n))).unwrap();
         (i, "streamed item")
     }
 
@@ -621,7 +2068,8 @@ mod tests {
 
     fn new_published_item(n: u32) -> Item {
         let mut i = new_item(n);
-        i.video_published_at = parse_optional_time(Some(&format!("2021-09-30T10:56:0{}+01:00", n)));
+        i.video_published_at =
+            parse_optional_time(Some(&format!("2021-09-30T10:56:0{}+01:00", n))).unwrap();
         i
     }
 
@@ -635,16 +2083,17 @@ mod tests {
         (new_item(n), "invalid item")
     }
 
-    fn new_item(n: u32) -> Item {
-        assert!(n <= 9);
-        Item {
-            video_id: format!("v{}", n).to_owned(),
-            playlist_item_id: format!("pii{}", n).to_owned(),
-            title: format!("video {}", n).to_owned(),
+    fn max_playable(max: usize) -> Config {
+        Config {
+            max_playable: Some(max),
             ..Default::default()
         }
     }
 
+    fn video_ids(items: &[Item]) -> Vec<&str> {
+        items.iter().map(|i| i.video_id.as_str()).collect()
+    }
+
     fn assert_video_ids(v: Vec<Item>, expected: Vec<&str>) {
         assert_video_ids_with_message(v, expected, &"".to_string());
     }
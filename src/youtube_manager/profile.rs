@@ -0,0 +1,147 @@
+use super::error::{Error, Result};
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+/// DEFAULT_PROFILE is the name of the profile used when none is specified.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// TOKEN_CACHE is the name of the file, in a profile's directory, in which the profile's tokens are cached.
+const TOKEN_CACHE: &str = "tokencache.json";
+
+/// Profiles stores the cached tokens of each named profile in a directory of its own, so that several
+/// accounts can be used side by side and the tokens do not depend on the current directory.
+pub struct Profiles {
+    dir: PathBuf,
+}
+
+impl Profiles {
+    /// new constructs a Profiles which stores profiles in the given directory.
+    pub fn new(dir: &Path) -> Profiles {
+        Profiles {
+            dir: dir.to_owned(),
+        }
+    }
+
+    /// default_dir returns the directory in which profiles are stored by default: playlist-manager/profiles
+    /// under the XDG state directory, which is $XDG_STATE_HOME or, if that is not set, ~/.local/state. On
+    /// Windows, the local application data directory is used instead.
+    pub fn default_dir() -> Result<PathBuf> {
+        let state_dir = env::var_os("XDG_STATE_HOME")
+            .filter(|d| !d.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| {
+                if cfg!(windows) {
+                    env::var_os("LOCALAPPDATA").map(PathBuf::from)
+                } else {
+                    env::var_os("HOME").map(|home| Path::new(&home).join(".local").join("state"))
+                }
+            })
+            .ok_or_else(|| {
+                Error::BadInput(
+                    "couldn't find a directory for profiles: set XDG_STATE_HOME or HOME"
+                        .to_string(),
+                )
+            })?;
+        Ok(state_dir.join("playlist-manager").join("profiles"))
+    }
+
+    /// token_cache returns the path of the token cache of the profile with the given name. An error is
+    /// returned if the name is not valid.
+    pub fn token_cache(self: &Self, name: &str) -> Result<PathBuf> {
+        Ok(self.profile_dir(name)?.join(TOKEN_CACHE))
+    }
+
+    /// list returns the names of the profiles which have cached tokens, in alphabetical order.
+    pub fn list(self: &Self) -> Result<Vec<String>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+        let mut names = vec![];
+        for entry in entries {
+            let entry = entry?;
+            if let Some(name) = entry.file_name().to_str() {
+                if entry.path().join(TOKEN_CACHE).is_file() {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// remove deletes the profile with the given name, together with its cached tokens. An error is returned if
+    /// there is no such profile.
+    pub fn remove(self: &Self, name: &str) -> Result<()> {
+        match fs::remove_dir_all(self.profile_dir(name)?) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Err(Error::BadInput(format!(
+                "there is no profile named {}",
+                name
+            ))),
+            result => Ok(result?),
+        }
+    }
+
+    fn profile_dir(self: &Self, name: &str) -> Result<PathBuf> {
+        let valid = !name.is_empty()
+            && !name.starts_with('.')
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.');
+        if valid {
+            Ok(self.dir.join(name))
+        } else {
+            Err(Error::BadInput(format!(
+                "invalid profile name {:?}: use letters, digits, '-', '_', and '.'",
+                name
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profiles_are_listed_and_removed() {
+        let dir = std::env::temp_dir().join(format!("profiles-test-{}", std::process::id()));
+        let profiles = Profiles::new(&dir);
+        assert_eq!(profiles.list().unwrap(), Vec::<String>::new());
+
+        for name in &["work", "default"] {
+            let path = profiles.token_cache(name).unwrap();
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, "{}").unwrap();
+        }
+        // directories without a token cache are not profiles
+        fs::create_dir_all(dir.join("empty")).unwrap();
+        assert_eq!(profiles.list().unwrap(), vec!["default", "work"]);
+
+        profiles.remove("work").unwrap();
+        let listed = profiles.list();
+        let removed_again = profiles.remove("work");
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(listed.unwrap(), vec!["default"]);
+        assert!(matches!(removed_again, Err(Error::BadInput(_))));
+    }
+
+    #[test]
+    fn profile_names_are_validated() {
+        let profiles = Profiles::new(Path::new("profiles"));
+        assert_eq!(
+            profiles.token_cache("my-channel_2.0").unwrap(),
+            Path::new("profiles/my-channel_2.0/tokencache.json")
+        );
+        for name in &["", ".", "..", "a/b", "a\\b", ".hidden", "a b"] {
+            assert!(
+                matches!(profiles.token_cache(name), Err(Error::BadInput(_))),
+                "{}",
+                name
+            );
+        }
+    }
+}
@@ -0,0 +1,279 @@
+use super::backend::PlaylistBackend;
+use super::error::{Error, Result};
+use async_trait::async_trait;
+use google_youtube3::api::{PlaylistItemListResponse, Video};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+/// Operation is a YouTube Data API operation used to manage playlists.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Operation {
+    ListItems,
+    ListVideos,
+    Insert,
+    Update,
+    Delete,
+}
+
+impl Operation {
+    /// cost returns the number of quota units used by a call of the operation.
+    pub fn cost(self: Self) -> u64 {
+        match self {
+            Operation::ListItems | Operation::ListVideos => 1,
+            Operation::Insert | Operation::Update | Operation::Delete => 50,
+        }
+    }
+
+    /// is_write returns true if and only if the operation changes a playlist.
+    pub fn is_write(self: Self) -> bool {
+        self.cost() > 1
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::ListItems => write!(f, "playlistItems.list"),
+            Operation::ListVideos => write!(f, "videos.list"),
+            Operation::Insert => write!(f, "playlistItems.insert"),
+            Operation::Update => write!(f, "playlistItems.update"),
+            Operation::Delete => write!(f, "playlistItems.delete"),
+        }
+    }
+}
+
+/// Usage counts the calls of each operation which have been made, or are planned.
+#[derive(Default, Clone, PartialEq, Debug)]
+pub struct Usage {
+    calls: BTreeMap<Operation, u64>,
+}
+
+impl Usage {
+    /// add counts the given number of calls of the given operation.
+    pub fn add(self: &mut Self, operation: Operation, calls: u64) {
+        if calls > 0 {
+            *self.calls.entry(operation).or_default() += calls;
+        }
+    }
+
+    /// units returns the total number of quota units used by the calls.
+    pub fn units(self: &Self) -> u64 {
+        self.calls.iter().map(|(op, n)| op.cost() * n).sum()
+    }
+}
+
+/// A usage is described by its total cost followed by the number of calls of each operation, e.g.
+/// "101 quota unit(s) (playlistItems.list: 1 call(s), playlistItems.update: 2 call(s))".
+impl fmt::Display for Usage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} quota unit(s)", self.units())?;
+        if !self.calls.is_empty() {
+            let calls: Vec<String> = self
+                .calls
+                .iter()
+                .map(|(op, n)| format!("{}: {} call(s)", op, n))
+                .collect();
+            write!(f, " ({})", calls.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Quota records the quota used by a run and enforces an optional budget on it. Clones share the same record,
+/// so a clone can be used to report the quota used through another clone. The budget applies to the quota used
+/// since the start of the current budget period, which is the start of the run unless a new period is started.
+#[derive(Clone)]
+pub struct Quota {
+    budget: Option<u64>,
+    used: Arc<Mutex<Usage>>,
+    /// period_start is the number of quota units which had been used when the current budget period started.
+    period_start: Arc<Mutex<u64>>,
+}
+
+impl Quota {
+    /// new constructs a Quota with no usage and the given budget, in quota units. There is no limit if budget
+    /// is None.
+    pub fn new(budget: Option<u64>) -> Quota {
+        Quota {
+            budget: budget,
+            used: Arc::new(Mutex::new(Default::default())),
+            period_start: Arc::new(Mutex::new(0)),
+        }
+    }
+
+    /// start_period starts a new budget period, so that the whole budget is available again. The calls made in
+    /// earlier periods are still included in used.
+    pub fn start_period(self: &Self) {
+        *self.period_start.lock().unwrap() = self.used().units();
+    }
+
+    /// used returns the calls made so far.
+    pub fn used(self: &Self) -> Usage {
+        self.used.lock().unwrap().clone()
+    }
+
+    /// check returns an error if the given planned calls would take the current budget period over budget.
+    pub fn check(self: &Self, planned: &Usage) -> Result<()> {
+        let used = self.used().units() - *self.period_start.lock().unwrap();
+        match self.budget {
+            Some(budget) if used + planned.units() > budget => Err(Error::Quota(format!(
+                "{} would exceed the budget of {} quota unit(s), of which {} have been used",
+                planned, budget, used
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// record counts a call of the given operation. Calls which change a playlist are refused, without being
+    /// counted, if they would take the run over budget. Other calls are always allowed so that the state of a
+    /// playlist can be reported.
+    fn record(self: &Self, operation: Operation) -> Result<()> {
+        if operation.is_write() {
+            let mut planned = Usage::default();
+            planned.add(operation, 1);
+            self.check(&planned)?;
+        }
+        self.used.lock().unwrap().add(operation, 1);
+        Ok(())
+    }
+}
+
+struct MeteredBackend<B: PlaylistBackend> {
+    backend: B,
+    quota: Quota,
+}
+
+/// meter returns a PlaylistBackend which records the calls made to the given backend in the given quota and
+/// refuses to make calls which would exceed its budget.
+pub fn meter<B: PlaylistBackend>(backend: B, quota: Quota) -> impl PlaylistBackend {
+    MeteredBackend {
+        backend: backend,
+        quota: quota,
+    }
+}
+
+#[async_trait]
+impl<B: PlaylistBackend> PlaylistBackend for MeteredBackend<B> {
+    async fn list_items(
+        self: &Self,
+        playlist_id: &str,
+        page_token: &Option<String>,
+        etag: Option<&str>,
+    ) -> Result<Option<PlaylistItemListResponse>> {
+        // Conditional requests use quota even if the page has not changed.
+        self.quota.record(Operation::ListItems)?;
+        self.backend.list_items(playlist_id, page_token, etag).await
+    }
+
+    async fn videos(self: &Self, video_ids: &[String]) -> Result<Vec<Video>> {
+        self.quota.record(Operation::ListVideos)?;
+        self.backend.videos(video_ids).await
+    }
+
+    async fn update_position(
+        self: &Self,
+        playlist_id: &str,
+        playlist_item_id: &str,
+        video_id: &str,
+        position: u32,
+    ) -> Result<()> {
+        self.quota.record(Operation::Update)?;
+        self.backend
+            .update_position(playlist_id, playlist_item_id, video_id, position)
+            .await
+    }
+
+    async fn insert(
+        self: &Self,
+        playlist_id: &str,
+        video_id: &str,
+        position: Option<u32>,
+    ) -> Result<String> {
+        self.quota.record(Operation::Insert)?;
+        self.backend.insert(playlist_id, video_id, position).await
+    }
+
+    async fn delete(self: &Self, playlist_item_id: &str) -> Result<()> {
+        self.quota.record(Operation::Delete)?;
+        self.backend.delete(playlist_item_id).await
+    }
+
+    fn check_quota(self: &Self, planned: &Usage) -> Result<()> {
+        self.quota.check(planned)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::memory::InMemoryBackend;
+    use super::super::testing::block_on;
+    use super::*;
+
+    #[test]
+    fn usage_counts_units_per_operation() {
+        let mut usage = Usage::default();
+        assert_eq!(usage.to_string(), "0 quota unit(s)");
+
+        usage.add(Operation::Update, 2);
+        usage.add(Operation::ListItems, 1);
+        usage.add(Operation::Delete, 0);
+        usage.add(Operation::Update, 1);
+        assert_eq!(usage.units(), 151);
+        assert_eq!(
+            usage.to_string(),
+            "151 quota unit(s) (playlistItems.list: 1 call(s), playlistItems.update: 3 call(s))"
+        );
+    }
+
+    #[test]
+    fn metered_backend_refuses_writes_over_budget() {
+        let backend = InMemoryBackend::new();
+        let item1 = backend.add_item("pl1", "v1", "video 1", None);
+        let item2 = backend.add_item("pl1", "v2", "video 2", None);
+        let quota = Quota::new(Some(60));
+        let metered = meter(backend.clone(), quota.clone());
+
+        block_on(metered.list_items("pl1", &None, None)).unwrap();
+        block_on(metered.delete(&item1)).unwrap();
+        assert!(matches!(
+            block_on(metered.delete(&item2)),
+            Err(Error::Quota(_))
+        ));
+        assert_eq!(backend.video_ids("pl1"), vec!["v2"]);
+
+        // reads are still allowed
+        block_on(metered.list_items("pl1", &None, None)).unwrap();
+        let mut expected = Usage::default();
+        expected.add(Operation::ListItems, 2);
+        expected.add(Operation::Delete, 1);
+        assert_eq!(quota.used(), expected);
+    }
+
+    #[test]
+    fn no_budget_means_no_limit() {
+        let mut planned = Usage::default();
+        planned.add(Operation::Insert, 1000);
+        assert!(Quota::new(None).check(&planned).is_ok());
+        assert!(Quota::new(Some(50_000)).check(&planned).is_ok());
+        assert!(Quota::new(Some(49_999)).check(&planned).is_err());
+    }
+
+    #[test]
+    fn new_budget_period_makes_the_whole_budget_available() {
+        let backend = InMemoryBackend::new();
+        let item1 = backend.add_item("pl1", "v1", "video 1", None);
+        let item2 = backend.add_item("pl1", "v2", "video 2", None);
+        let quota = Quota::new(Some(50));
+        let metered = meter(backend.clone(), quota.clone());
+
+        block_on(metered.delete(&item1)).unwrap();
+        assert!(block_on(metered.delete(&item2)).is_err());
+        quota.start_period();
+        block_on(metered.delete(&item2)).unwrap();
+        assert_eq!(quota.used().units(), 100);
+        assert!(backend.video_ids("pl1").is_empty());
+    }
+}
@@ -0,0 +1,193 @@
+use super::playlist::Item;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Move moves a playlist item to a new position in the playlist.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Move {
+    pub item: Item,
+    pub position: u32,
+}
+
+/// moves returns a minimal sequence of moves which reorders the items in current into the order of the items
+/// in target. current and target must contain the same items, identified by playlist item id.
+///
+/// The items in a longest increasing subsequence of current (with respect to the order of target) are
+/// already in the correct relative order and stay put. Every other item is moved, in target order, to the
+/// position just after its predecessor in target.
+pub fn moves(current: &[Item], target: &[Item]) -> Vec<Move> {
+    assert_eq!(current.len(), target.len(), "items differ");
+
+    let target_index: HashMap<&str, usize> = target
+        .iter()
+        .enumerate()
+        .map(|(n, i)| (i.playlist_item_id.as_str(), n))
+        .collect();
+    let order: Vec<usize> = current
+        .iter()
+        .map(|i| {
+            *target_index
+                .get(i.playlist_item_id.as_str())
+                .expect("items differ")
+        })
+        .collect();
+    let fixed: HashSet<usize> = longest_increasing_subsequence(&order)
+        .into_iter()
+        .map(|n| order[n])
+        .collect();
+
+    // Simulate the moves to determine the position of each one.
+    let mut ids: Vec<&str> = current
+        .iter()
+        .map(|i| i.playlist_item_id.as_str())
+        .collect();
+    let mut moves = vec![];
+    for (n, item) in target.iter().enumerate() {
+        if fixed.contains(&n) {
+            continue;
+        }
+        let id = item.playlist_item_id.as_str();
+        ids.retain(|&other| other != id);
+        let position = if n == 0 {
+            0
+        } else {
+            let predecessor = target[n - 1].playlist_item_id.as_str();
+            ids.iter().position(|&other| other == predecessor).unwrap() + 1
+        };
+        ids.insert(position, id);
+        moves.push(Move {
+            item: item.clone(),
+            position: position as u32,
+        });
+    }
+    moves
+}
+
+/// apply applies the given moves to the given items in the same way that YouTube applies position updates
+/// to a playlist.
+pub fn apply(items: &mut Vec<Item>, moves: &[Move]) {
+    for m in moves {
+        if let Some(n) = items
+            .iter()
+            .position(|i| i.playlist_item_id == m.item.playlist_item_id)
+        {
+            let item = items.remove(n);
+            items.insert((m.position as usize).min(items.len()), item);
+        }
+    }
+}
+
+/// longest_increasing_subsequence returns the indices of a longest strictly increasing subsequence of seq.
+fn longest_increasing_subsequence(seq: &[usize]) -> Vec<usize> {
+    // tails[k] is the index of the smallest value which ends an increasing subsequence of length k + 1
+    let mut tails: Vec<usize> = vec![];
+    let mut predecessors: Vec<Option<usize>> = vec![None; seq.len()];
+    for (n, &x) in seq.iter().enumerate() {
+        let len = tails.partition_point(|&t| seq[t] < x);
+        if len > 0 {
+            predecessors[n] = Some(tails[len - 1]);
+        }
+        if len == tails.len() {
+            tails.push(n);
+        } else {
+            tails[len] = n;
+        }
+    }
+
+    let mut lis = vec![];
+    let mut next = tails.last().copied();
+    while let Some(n) = next {
+        lis.push(n);
+        next = predecessors[n];
+    }
+    lis.reverse();
+    lis
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::new_items;
+    use super::*;
+
+    #[test]
+    fn no_moves_when_already_in_order() {
+        assert_eq!(moves(&[], &[]), vec![]);
+        let items = new_items(&[1, 2, 3]);
+        assert_eq!(moves(&items, &items), vec![]);
+    }
+
+    #[test]
+    fn one_move_for_one_item_out_of_place() {
+        let m = check_moves(&[4, 1, 2, 3], &[1, 2, 3, 4]);
+        assert_eq!(m.len(), 1);
+        assert_eq!(m[0].item.video_id, "v4");
+        assert_eq!(m[0].position, 3);
+
+        let m = check_moves(&[1, 2, 3, 4], &[4, 1, 2, 3]);
+        assert_eq!(m.len(), 1);
+        assert_eq!(m[0].item.video_id, "v4");
+        assert_eq!(m[0].position, 0);
+    }
+
+    #[test]
+    fn reversal() {
+        assert_eq!(check_moves(&[5, 4, 3, 2, 1], &[1, 2, 3, 4, 5]).len(), 4);
+    }
+
+    #[test]
+    fn moves_are_positioned_relative_to_items_already_placed() {
+        assert_eq!(check_moves(&[2, 3, 1], &[1, 3, 2]).len(), 2);
+        assert_eq!(check_moves(&[3, 1, 4, 2], &[1, 2, 3, 4]).len(), 2);
+    }
+
+    #[test]
+    fn permutations_use_the_minimum_number_of_moves() {
+        // a simple linear congruential generator keeps the test deterministic
+        let mut seed: u64 = 42;
+        let mut random = |bound: usize| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            ((seed >> 33) as usize) % bound
+        };
+        for len in 0..9 {
+            for _ in 0..20 {
+                let mut current: Vec<u32> = (1..=len).collect();
+                for n in (1..current.len()).rev() {
+                    current.swap(n, random(n + 1));
+                }
+                let target: Vec<u32> = (1..=len).collect();
+                let order: Vec<usize> = current.iter().map(|&n| (n - 1) as usize).collect();
+                let m = check_moves(&current, &target);
+                assert_eq!(
+                    m.len(),
+                    len as usize - longest_increasing_subsequence(&order).len()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn longest_increasing_subsequence_of_sequences() {
+        assert_eq!(longest_increasing_subsequence(&[]), Vec::<usize>::new());
+        assert_eq!(longest_increasing_subsequence(&[0]), vec![0]);
+        assert_eq!(longest_increasing_subsequence(&[2, 1, 0]).len(), 1);
+        assert_eq!(
+            longest_increasing_subsequence(&[3, 0, 4, 1, 2, 5]),
+            vec![1, 3, 4, 5]
+        );
+    }
+
+    /// check_moves computes the moves from current to target, checks that applying the moves produces
+    /// target, and returns the moves.
+    fn check_moves(current: &[u32], target: &[u32]) -> Vec<Move> {
+        let current = new_items(current);
+        let target = new_items(target);
+        let m = moves(&current, &target);
+
+        let mut playlist = current.clone();
+        apply(&mut playlist, &m);
+        assert_eq!(playlist, target);
+        m
+    }
+}
@@ -0,0 +1,434 @@
+use super::backend::PlaylistBackend;
+use super::error::Result;
+use super::quota::{Operation, Usage};
+use async_trait::async_trait;
+use google_youtube3::api::{PlaylistItemListResponse, Video};
+use rand::Rng;
+use std::{future::Future, time::Duration};
+
+/// RetryPolicy determines how calls which fail with a retryable error are retried. The delay before each retry
+/// is doubled, up to max_delay, and then randomly reduced by up to a half so that clients which failed at the
+/// same time do not retry at the same time.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct RetryPolicy {
+    /// max_retries is the number of times a call is retried before its error is returned.
+    pub max_retries: u32,
+    /// initial_delay is the delay before the first retry, before it is randomly reduced.
+    pub initial_delay: Duration,
+    /// max_delay is the maximum delay before any retry.
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 5,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(32),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// delay returns the delay before the given retry, counting from zero.
+    pub fn delay(self: &Self, retry: u32) -> Duration {
+        let delay = self
+            .initial_delay
+            .checked_mul(2u32.saturating_pow(retry))
+            .map_or(self.max_delay, |d| d.min(self.max_delay));
+        delay.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+}
+
+struct RetryingBackend<B: PlaylistBackend> {
+    backend: B,
+    policy: RetryPolicy,
+}
+
+/// retry returns a PlaylistBackend which retries calls to the given backend according to the given policy.
+///
+/// A write which fails with a server error may nevertheless have been made. So a retried deletion which finds
+/// the item already gone has succeeded, and before an insert is retried the playlist is checked for the video at
+/// the position it was to be inserted so that it is not added twice.
+pub fn retry<B: PlaylistBackend>(backend: B, policy: RetryPolicy) -> impl PlaylistBackend {
+    RetryingBackend {
+        backend: backend,
+        policy: policy,
+    }
+}
+
+impl<B: PlaylistBackend> RetryingBackend<B> {
+    /// call makes the given call of the given operation, retrying it for as long as it fails with a retryable
+    /// error and the policy allows.
+    async fn call<T, F, Fut>(self: &Self, operation: Operation, mut f: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut retry = 0;
+        loop {
+            match f().await {
+                Err(e) if e.is_retryable() && retry < self.policy.max_retries => {
+                    let delay = self.policy.delay(retry);
+                    eprintln!(
+                        "{} failed: {}. Retrying in {}...",
+                        operation,
+                        e,
+                        humantime::format_duration(Duration::from_millis(delay.as_millis() as u64))
+                    );
+                    tokio::time::sleep(delay).await;
+                    retry += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// inserted_item returns the id of the item of the given playlist which an earlier attempt to insert the
+    /// given video at the given position, or at the end of the playlist, would have added, if the item there
+    /// refers to the video. Items for the video elsewhere in the playlist are not taken to be the result of the
+    /// earlier attempt, since they may have been there before it.
+    async fn inserted_item(
+        self: &Self,
+        playlist_id: &str,
+        video_id: &str,
+        position: Option<u32>,
+    ) -> Result<Option<String>> {
+        let mut items = vec![];
+        let mut page_token = None;
+        loop {
+            let res = match self
+                .backend
+                .list_items(playlist_id, &page_token, None)
+                .await?
+            {
+                Some(res) => res,
+                None => return Ok(None),
+            };
+            items.extend(res.items.unwrap_or_default());
+            page_token = res.next_page_token;
+            if page_token.is_none() {
+                break;
+            }
+        }
+
+        let item = match position {
+            Some(position) => items.into_iter().nth(position as usize),
+            None => items.pop(),
+        };
+        Ok(item
+            .filter(|item| {
+                item.content_details
+                    .as_ref()
+                    .and_then(|details| details.video_id.as_deref())
+                    == Some(video_id)
+            })
+            .and_then(|item| item.id))
+    }
+}
+
+#[async_trait]
+impl<B: PlaylistBackend> PlaylistBackend for RetryingBackend<B> {
+    async fn list_items(
+        self: &Self,
+        playlist_id: &str,
+        page_token: &Option<String>,
+        etag: Option<&str>,
+    ) -> Result<Option<PlaylistItemListResponse>> {
+        self.call(Operation::ListItems, || {
+            self.backend.list_items(playlist_id, page_token, etag)
+        })
+        .await
+    }
+
+    async fn videos(self: &Self, video_ids: &[String]) -> Result<Vec<Video>> {
+        self.call(Operation::ListVideos, || self.backend.videos(video_ids))
+            .await
+    }
+
+    async fn update_position(
+        self: &Self,
+        playlist_id: &str,
+        playlist_item_id: &str,
+        video_id: &str,
+        position: u32,
+    ) -> Result<()> {
+        self.call(Operation::Update, || {
+            self.backend
+                .update_position(playlist_id, playlist_item_id, video_id, position)
+        })
+        .await
+    }
+
+    async fn insert(
+        self: &Self,
+        playlist_id: &str,
+        video_id: &str,
+        position: Option<u32>,
+    ) -> Result<String> {
+        let mut attempts = 0;
+        self.call(Operation::Insert, || {
+            attempts += 1;
+            let retried = attempts > 1;
+            async move {
+                if retried {
+                    if let Some(id) = self.inserted_item(playlist_id, video_id, position).await? {
+                        return Ok(id);
+                    }
+                }
+                self.backend.insert(playlist_id, video_id, position).await
+            }
+        })
+        .await
+    }
+
+    async fn delete(self: &Self, playlist_item_id: &str) -> Result<()> {
+        let mut attempts = 0;
+        self.call(Operation::Delete, || {
+            attempts += 1;
+            let retried = attempts > 1;
+            async move {
+                match self.backend.delete(playlist_item_id).await {
+                    Err(e) if retried && e.is_not_found() => Ok(()),
+                    result => result,
+                }
+            }
+        })
+        .await
+    }
+
+    fn check_quota(self: &Self, planned: &Usage) -> Result<()> {
+        self.backend.check_quota(planned)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::error::Error;
+    use super::super::memory::InMemoryBackend;
+    use super::super::testing::block_on;
+    use super::*;
+    use google_youtube3::client;
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn delays_grow_exponentially_up_to_the_maximum() {
+        let policy = RetryPolicy {
+            max_retries: 10,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(5),
+        };
+        for (retry, max) in [(0, 1), (1, 2), (2, 4), (3, 5), (40, 5)].iter() {
+            let delay = policy.delay(*retry);
+            let max = Duration::from_secs(*max);
+            assert!(delay >= max / 2 && delay <= max, "{:?} {:?}", delay, max);
+        }
+    }
+
+    #[test]
+    fn retryable_errors_are_retried() {
+        let backend = FailingBackend::new(vec![error(503, "backendError"), error(500, "")]);
+        let retrying = retry(backend.clone(), quick_policy(2));
+        block_on(retrying.delete("pi1")).unwrap();
+        assert_eq!(backend.calls(), 3);
+        assert_eq!(backend.inner.video_ids("pl1"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn retries_are_limited() {
+        let backend = FailingBackend::new((0..3).map(|_| error(503, "backendError")).collect());
+        let retrying = retry(backend.clone(), quick_policy(2));
+        assert!(matches!(
+            block_on(retrying.delete("pi1")),
+            Err(Error::Api(_))
+        ));
+        assert_eq!(backend.calls(), 3);
+    }
+
+    #[test]
+    fn retried_deletion_of_a_deleted_item_succeeds() {
+        let backend = FailingBackend::new(vec![error(503, "backendError")]).after_call();
+        let retrying = retry(backend.clone(), quick_policy(2));
+        block_on(retrying.delete("pi1")).unwrap();
+        assert_eq!(backend.calls(), 2);
+        assert_eq!(backend.inner.video_ids("pl1"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn retried_insert_does_not_add_the_video_twice() {
+        let backend = FailingBackend::new(vec![error(503, "backendError")]).after_call();
+        backend.inner.add_video(Video {
+            id: Some("v2".to_string()),
+            ..Default::default()
+        });
+        let retrying = retry(backend.clone(), quick_policy(2));
+        let id = block_on(retrying.insert("pl1", "v2", None)).unwrap();
+        assert_eq!(backend.calls(), 1);
+        assert_eq!(backend.inner.video_ids("pl1"), vec!["v1", "v2"]);
+        assert_eq!(
+            block_on(playlist_item_ids(&backend.inner, "pl1"))[1],
+            Some(id)
+        );
+    }
+
+    #[test]
+    fn retried_insert_ignores_the_video_elsewhere_in_the_playlist() {
+        for position in vec![None, Some(1)] {
+            let backend = FailingBackend::new(vec![error(503, "backendError")]);
+            backend.inner.add_item("pl1", "v2", "video 2", None);
+            backend.inner.add_video(Video {
+                id: Some("v1".to_string()),
+                ..Default::default()
+            });
+            let retrying = retry(backend.clone(), quick_policy(2));
+            block_on(retrying.insert("pl1", "v1", position)).unwrap();
+            assert_eq!(backend.calls(), 2);
+            assert_eq!(backend.inner.video_ids("pl1").len(), 3);
+        }
+    }
+
+    #[test]
+    fn failed_insert_is_retried() {
+        let backend = FailingBackend::new(vec![error(503, "backendError")]);
+        backend.inner.add_video(Video {
+            id: Some("v2".to_string()),
+            ..Default::default()
+        });
+        let retrying = retry(backend.clone(), quick_policy(2));
+        block_on(retrying.insert("pl1", "v2", None)).unwrap();
+        assert_eq!(backend.calls(), 2);
+        assert_eq!(backend.inner.video_ids("pl1"), vec!["v1", "v2"]);
+    }
+
+    #[test]
+    fn quota_and_auth_errors_are_not_retried() {
+        for e in vec![error(403, "quotaExceeded"), error(401, "authError")] {
+            let backend = FailingBackend::new(vec![e]);
+            let retrying = retry(backend.clone(), quick_policy(2));
+            assert!(block_on(retrying.delete("pi1")).is_err());
+            assert_eq!(backend.calls(), 1);
+        }
+    }
+
+    fn quick_policy(max_retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_retries: max_retries,
+            initial_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(2),
+        }
+    }
+
+    fn error(code: u16, reason: &str) -> Error {
+        Error::from(client::Error::BadRequest(json!({
+            "error": {
+                "code": code,
+                "message": "request failed",
+                "errors": [{"domain": "youtube", "reason": reason}]
+            }
+        })))
+    }
+
+    async fn playlist_item_ids(
+        backend: &InMemoryBackend,
+        playlist_id: &str,
+    ) -> Vec<Option<String>> {
+        let res = backend.list_items(playlist_id, &None, None).await.unwrap();
+        res.unwrap()
+            .items
+            .unwrap_or_default()
+            .into_iter()
+            .map(|item| item.id)
+            .collect()
+    }
+
+    /// FailingBackend fails insertions and deletions with the given errors, in turn, before passing them on to
+    /// an in-memory backend with a playlist containing a single item. If after_call is set, the call is passed
+    /// on before the error is returned, as when a write is made but the response is lost.
+    #[derive(Clone)]
+    struct FailingBackend {
+        inner: InMemoryBackend,
+        errors: Arc<Mutex<Vec<Error>>>,
+        calls: Arc<Mutex<usize>>,
+        after_call: bool,
+    }
+
+    impl FailingBackend {
+        fn new(mut errors: Vec<Error>) -> FailingBackend {
+            let inner = InMemoryBackend::new();
+            inner.add_item("pl1", "v1", "video 1", None);
+            errors.reverse();
+            FailingBackend {
+                inner: inner,
+                errors: Arc::new(Mutex::new(errors)),
+                calls: Arc::new(Mutex::new(0)),
+                after_call: false,
+            }
+        }
+
+        fn after_call(self: Self) -> FailingBackend {
+            FailingBackend {
+                after_call: true,
+                ..self
+            }
+        }
+
+        fn calls(self: &Self) -> usize {
+            *self.calls.lock().unwrap()
+        }
+
+        /// fail makes the given call, unless it is to fail before being made, and returns the next error, if
+        /// any, or the result of the call.
+        async fn fail<T, Fut: Future<Output = Result<T>>>(self: &Self, call: Fut) -> Result<T> {
+            *self.calls.lock().unwrap() += 1;
+            let error = self.errors.lock().unwrap().pop();
+            match error {
+                Some(e) if self.after_call => call.await.and(Err(e)),
+                Some(e) => Err(e),
+                None => call.await,
+            }
+        }
+    }
+
+    #[async_trait]
+    impl PlaylistBackend for FailingBackend {
+        async fn list_items(
+            self: &Self,
+            playlist_id: &str,
+            page_token: &Option<String>,
+            etag: Option<&str>,
+        ) -> Result<Option<PlaylistItemListResponse>> {
+            self.inner.list_items(playlist_id, page_token, etag).await
+        }
+
+        async fn videos(self: &Self, video_ids: &[String]) -> Result<Vec<Video>> {
+            self.inner.videos(video_ids).await
+        }
+
+        async fn update_position(
+            self: &Self,
+            playlist_id: &str,
+            playlist_item_id: &str,
+            video_id: &str,
+            position: u32,
+        ) -> Result<()> {
+            self.inner
+                .update_position(playlist_id, playlist_item_id, video_id, position)
+                .await
+        }
+
+        async fn insert(
+            self: &Self,
+            playlist_id: &str,
+            video_id: &str,
+            position: Option<u32>,
+        ) -> Result<String> {
+            self.fail(self.inner.insert(playlist_id, video_id, position))
+                .await
+        }
+
+        async fn delete(self: &Self, playlist_item_id: &str) -> Result<()> {
+            self.fail(self.inner.delete(playlist_item_id)).await
+        }
+    }
+}
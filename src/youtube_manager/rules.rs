@@ -0,0 +1,308 @@
+use super::playlist::{Item, ItemProperties};
+use super::sort::Category;
+use chrono::{DateTime, Duration, Utc};
+use regex::Regex;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{collections::HashMap, fmt};
+
+/// Rule is a reason for removing items from a playlist when it is pruned.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(tag = "rule", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum Rule {
+    /// Blocked removes blocked videos.
+    Blocked,
+    /// Invalid removes videos which are neither scheduled nor published, including deleted videos.
+    Invalid,
+    /// OlderThan removes videos which were streamed or uploaded more than the given number of days ago.
+    OlderThan { days: i64 },
+    /// TitleMatches removes videos whose titles match the given regular expression.
+    TitleMatches { pattern: Pattern },
+    /// MaxPerCategory removes videos of the given category beyond the first max in sorted order.
+    MaxPerCategory { category: Category, max: usize },
+    /// MissedStream removes scheduled videos which have not been streamed more than the given number of hours
+    /// after their scheduled start time.
+    MissedStream {
+        #[serde(default)]
+        grace_hours: i64,
+    },
+}
+
+/// The description of a rule is used as the reason for removing an item, e.g. in "Removing blocked video".
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rule::Blocked => write!(f, "blocked"),
+            Rule::Invalid => write!(f, "unscheduled and unpublished or deleted"),
+            Rule::OlderThan { days } => write!(f, "older than {} days", days),
+            Rule::TitleMatches { pattern } => write!(f, "title matching /{}/", pattern.0),
+            Rule::MaxPerCategory { category, max } => {
+                write!(f, "surplus (more than {} {})", max, category)
+            }
+            Rule::MissedStream { .. } => write!(f, "missed stream"),
+        }
+    }
+}
+
+impl Rule {
+    /// matches returns true if and only if the rule removes the given item regardless of the other items in
+    /// the playlist. Rules which limit the number of items never match individual items.
+    fn matches(self: &Self, item: &Item, now: DateTime<Utc>) -> bool {
+        match self {
+            Rule::Blocked => item.blocked,
+            Rule::Invalid => {
+                item.scheduled_start_time.is_none() && item.video_published_at.is_none()
+            }
+            Rule::OlderThan { days } => match (item.available_time(), before(now, *days, DAY_MS)) {
+                (Some(t), Some(limit)) => t < limit,
+                _ => false,
+            },
+            Rule::TitleMatches { pattern } => pattern.0.is_match(&item.title),
+            Rule::MaxPerCategory { .. } => false,
+            Rule::MissedStream { grace_hours } => {
+                Category::of(item) == Category::Scheduled
+                    && match (
+                        item.scheduled_start_time,
+                        before(now, *grace_hours, HOUR_MS),
+                    ) {
+                        (Some(t), Some(limit)) => t < limit,
+                        _ => false,
+                    }
+            }
+        }
+    }
+}
+
+const HOUR_MS: i64 = 60 * 60 * 1000;
+const DAY_MS: i64 = 24 * HOUR_MS;
+
+/// before returns the time the given number of units, each of the given number of milliseconds, before now,
+/// or None if that is out of range. A rule whose limit is out of range never matches, rather than panicking
+/// on a huge number of days or hours in a config file.
+fn before(now: DateTime<Utc>, n: i64, unit_ms: i64) -> Option<DateTime<Utc>> {
+    n.checked_mul(unit_ms)
+        .and_then(|ms| now.checked_sub_signed(Duration::milliseconds(ms)))
+}
+
+/// RuleSet is a list of pruning rules. The default rule set removes blocked and invalid videos.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(transparent)]
+pub struct RuleSet {
+    pub rules: Vec<Rule>,
+}
+
+impl Default for RuleSet {
+    fn default() -> Self {
+        RuleSet {
+            rules: vec![Rule::Blocked, Rule::Invalid],
+        }
+    }
+}
+
+impl RuleSet {
+    /// pruner returns a Pruner which applies the rules at the given time.
+    pub fn pruner(self: &Self, now: DateTime<Utc>) -> Pruner<'_> {
+        Pruner {
+            rules: self,
+            now: now,
+            counts: HashMap::new(),
+        }
+    }
+}
+
+/// Pruner applies a rule set to the items of a playlist, in sorted order.
+pub struct Pruner<'a> {
+    rules: &'a RuleSet,
+    now: DateTime<Utc>,
+    counts: HashMap<Category, usize>,
+}
+
+impl<'a> Pruner<'a> {
+    /// reason returns None if the given item should be kept. Otherwise it returns the description of the
+    /// first rule which removes the item. Items must be passed in sorted order so that rules which limit the
+    /// number of items keep the first items.
+    pub fn reason(self: &mut Self, item: &Item) -> Option<String> {
+        if let Some(rule) = self.rules.rules.iter().find(|r| r.matches(item, self.now)) {
+            return Some(rule.to_string());
+        }
+
+        let category = Category::of(item);
+        let count = self.counts.entry(category).or_default();
+        *count += 1;
+        let n = *count;
+        self.rules
+            .rules
+            .iter()
+            .find(|r| matches!(r, Rule::MaxPerCategory { category: c, max } if *c == category && n > *max))
+            .map(|r| r.to_string())
+    }
+}
+
+/// Pattern is a regular expression which is written as a string in config files.
+#[derive(Clone, Debug)]
+pub struct Pattern(pub Regex);
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl Serialize for Pattern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Regex::new(&s).map(Pattern).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::new_item;
+    use super::*;
+
+    #[test]
+    fn default_rules_remove_blocked_and_invalid_videos() {
+        let rules = RuleSet::default();
+        let mut pruner = rules.pruner(now());
+        assert_eq!(
+            pruner.reason(&timed_item(Some(-1), None, true)),
+            Some("blocked".to_string())
+        );
+        assert_eq!(
+            pruner.reason(&timed_item(None, None, false)),
+            Some("unscheduled and unpublished or deleted".to_string())
+        );
+        assert_eq!(pruner.reason(&timed_item(Some(-100), None, false)), None);
+        assert_eq!(pruner.reason(&timed_item(None, Some(-100), false)), None);
+    }
+
+    #[test]
+    fn first_matching_rule_gives_the_reason() {
+        let rules = RuleSet {
+            rules: vec![
+                Rule::OlderThan { days: 7 },
+                Rule::TitleMatches {
+                    pattern: Pattern(Regex::new("(?i)trailer").unwrap()),
+                },
+                Rule::MissedStream { grace_hours: 24 },
+            ],
+        };
+        let mut pruner = rules.pruner(now());
+
+        let mut item = timed_item(Some(-8 * 24), None, false);
+        item.title = "Trailer".to_string();
+        assert_eq!(pruner.reason(&item), Some("older than 7 days".to_string()));
+
+        item.video_published_at = None;
+        item.scheduled_start_time = Some(now() - Duration::hours(2));
+        assert_eq!(
+            pruner.reason(&item),
+            Some("title matching /(?i)trailer/".to_string())
+        );
+
+        item.title = "Live".to_string();
+        assert_eq!(pruner.reason(&item), None);
+        item.scheduled_start_time = Some(now() - Duration::hours(25));
+        assert_eq!(pruner.reason(&item), Some("missed stream".to_string()));
+    }
+
+    #[test]
+    fn out_of_range_limits_never_match() {
+        let rules = RuleSet {
+            rules: vec![
+                Rule::OlderThan { days: i64::MAX },
+                Rule::OlderThan { days: i64::MIN },
+                Rule::MissedStream {
+                    grace_hours: i64::MAX,
+                },
+                Rule::MissedStream {
+                    grace_hours: i64::MAX / HOUR_MS,
+                },
+            ],
+        };
+        let mut pruner = rules.pruner(now());
+        assert_eq!(
+            pruner.reason(&timed_item(Some(-100_000), None, false)),
+            None
+        );
+        assert_eq!(
+            pruner.reason(&timed_item(None, Some(-100_000), false)),
+            None
+        );
+    }
+
+    #[test]
+    fn max_per_category_keeps_the_first_items() {
+        let rules = RuleSet {
+            rules: vec![
+                Rule::Blocked,
+                Rule::MaxPerCategory {
+                    category: Category::Viewable,
+                    max: 1,
+                },
+            ],
+        };
+        let mut pruner = rules.pruner(now());
+        // blocked items are not counted
+        assert!(pruner.reason(&timed_item(Some(-1), None, true)).is_some());
+        assert_eq!(pruner.reason(&timed_item(Some(-2), None, false)), None);
+        assert_eq!(pruner.reason(&timed_item(None, Some(1), false)), None);
+        assert_eq!(
+            pruner.reason(&timed_item(Some(-3), None, false)),
+            Some("surplus (more than 1 viewable)".to_string())
+        );
+    }
+
+    #[test]
+    fn rules_are_read_from_toml() {
+        let rules: RuleSet = toml::from_str::<HashMap<String, RuleSet>>(
+            r#"
+            [[rules]]
+            rule = "blocked"
+
+            [[rules]]
+            rule = "max-per-category"
+            category = "scheduled"
+            max = 2
+
+            [[rules]]
+            rule = "title-matches"
+            pattern = "^Rehearsal"
+            "#,
+        )
+        .unwrap()
+        .remove("rules")
+        .unwrap();
+        assert_eq!(rules.rules.len(), 3);
+        assert_eq!(
+            rules.rules[1],
+            Rule::MaxPerCategory {
+                category: Category::Scheduled,
+                max: 2
+            }
+        );
+
+        assert!(toml::from_str::<HashMap<String, RuleSet>>(
+            "[[rules]]\nrule = \"title-matches\"\npattern = \"(\""
+        )
+        .is_err());
+    }
+
+    fn now() -> DateTime<Utc> {
+        "2021-10-01T12:00:00Z".parse().unwrap()
+    }
+
+    /// timed_item returns an item published, or scheduled, the given number of hours from now.
+    fn timed_item(published: Option<i64>, scheduled: Option<i64>, blocked: bool) -> Item {
+        let mut i = new_item(1);
+        i.video_published_at = published.map(|h| now() + Duration::hours(h));
+        i.scheduled_start_time = scheduled.map(|h| now() + Duration::hours(h));
+        i.blocked = blocked;
+        i
+    }
+}
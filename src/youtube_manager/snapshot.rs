@@ -0,0 +1,65 @@
+use super::error::{Error, Result};
+use super::playlist::Item;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+/// Snapshot records the items of a playlist, in playlist order, so that the playlist can be restored later.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Snapshot {
+    /// playlist_id is the id of the playlist.
+    pub playlist_id: String,
+    /// taken_at is the time the snapshot was taken.
+    pub taken_at: DateTime<Utc>,
+    /// items are the items of the playlist, in playlist order.
+    pub items: Vec<Item>,
+}
+
+impl Snapshot {
+    /// new constructs a snapshot of the given items of the given playlist, taken now.
+    pub fn new(playlist_id: &str, items: Vec<Item>) -> Snapshot {
+        Snapshot {
+            playlist_id: playlist_id.to_owned(),
+            taken_at: Utc::now(),
+            items: items,
+        }
+    }
+
+    /// save writes the snapshot to the given file in JSON format.
+    pub fn save(self: &Self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| Error::BadInput(format!("couldn't serialize snapshot: {}", e)))?;
+        fs::write(path, json)?;
+        Ok(())
+    }
+
+    /// load reads a snapshot from the given file, which must have been written by save.
+    pub fn load(path: &Path) -> Result<Snapshot> {
+        let json = fs::read_to_string(path)?;
+        serde_json::from_str(&json).map_err(|e| {
+            Error::BadInput(format!("invalid snapshot file {}: {}", path.display(), e))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_and_load() {
+        let mut item = Item::default();
+        item.video_id = "v1".to_string();
+        item.playlist_item_id = "pii1".to_string();
+        item.title = "video 1".to_string();
+        item.actual_start_time = Some(Utc::now());
+        let snapshot = Snapshot::new("pl1", vec![item]);
+
+        let path =
+            std::env::temp_dir().join(format!("snapshot-test-{}.json", std::process::id()));
+        snapshot.save(&path).unwrap();
+        let loaded = Snapshot::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), snapshot);
+    }
+}
@@ -0,0 +1,267 @@
+use super::playlist::{Item, ItemProperties};
+use chrono::{DateTime, Utc};
+use serde::{
+    de::{value, DeserializeOwned, IntoDeserializer},
+    Deserialize, Serialize,
+};
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+/// Category classifies playlist items for sorting.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum Category {
+    /// Viewable items are streamed or uploaded, but not blocked.
+    Viewable,
+    /// Scheduled items have not been streamed yet, but have a scheduled start time.
+    Scheduled,
+    /// Invalid items have no time information, e.g. because the video has been deleted.
+    Invalid,
+    /// Blocked items are not viewable in the region of the playlist owner.
+    Blocked,
+}
+
+impl Category {
+    /// of returns the category of the given item.
+    pub fn of(item: &Item) -> Category {
+        if item.blocked {
+            Category::Blocked
+        } else if item.viewable() {
+            Category::Viewable
+        } else if item.scheduled_start_time.is_some() {
+            Category::Scheduled
+        } else {
+            Category::Invalid
+        }
+    }
+
+    /// time returns the time by which items in the category are ordered.
+    fn time(self: Self, item: &Item) -> Option<DateTime<Utc>> {
+        match self {
+            Category::Viewable => item.viewable_time(),
+            Category::Scheduled => item.scheduled_start_time,
+            Category::Invalid => None,
+            Category::Blocked => item.available_time(),
+        }
+    }
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Category::Viewable => write!(f, "viewable"),
+            Category::Scheduled => write!(f, "scheduled"),
+            Category::Invalid => write!(f, "invalid"),
+            Category::Blocked => write!(f, "blocked"),
+        }
+    }
+}
+
+/// Direction is the chronological order of items within a category.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum Direction {
+    /// Ascending orders items oldest first.
+    Ascending,
+    /// Descending orders items newest first.
+    Descending,
+}
+
+/// TieBreaker orders items of the same category which have the same time, or no time.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum TieBreaker {
+    /// Title orders items alphabetically by title.
+    Title,
+    /// DateAdded orders items by the time they were added to the playlist, in the policy's direction.
+    DateAdded,
+}
+
+/// SortPolicy determines the order of a sorted playlist. Items are ordered first by category, then by time
+/// in the given direction, and then by each tie-breaker in turn. Items which are still equal keep their
+/// relative order.
+///
+/// The default policy orders viewable items, then scheduled items, then invalid items, then blocked items,
+/// with newest items first in each category.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct SortPolicy {
+    /// categories lists every category exactly once, in sorted order.
+    pub categories: Vec<Category>,
+    pub direction: Direction,
+    pub tie_breakers: Vec<TieBreaker>,
+}
+
+impl Default for SortPolicy {
+    fn default() -> Self {
+        SortPolicy {
+            categories: vec![
+                Category::Viewable,
+                Category::Scheduled,
+                Category::Invalid,
+                Category::Blocked,
+            ],
+            direction: Direction::Descending,
+            tie_breakers: vec![],
+        }
+    }
+}
+
+impl SortPolicy {
+    /// validate checks that the policy lists every category exactly once.
+    pub fn validate(self: &Self) -> std::result::Result<(), String> {
+        for c in &SortPolicy::default().categories {
+            match self.categories.iter().filter(|&d| d == c).count() {
+                0 => return Err(format!("sort policy does not order {:?} items", c)),
+                1 => {}
+                _ => return Err(format!("sort policy orders {:?} items more than once", c)),
+            }
+        }
+        Ok(())
+    }
+
+    /// compare compares two items according to the policy.
+    pub fn compare(self: &Self, v: &Item, w: &Item) -> Ordering {
+        let (cv, cw) = (Category::of(v), Category::of(w));
+        self.rank(cv).cmp(&self.rank(cw)).then_with(|| {
+            self.directed(cv.time(v).cmp(&cw.time(w)))
+                .then_with(|| self.break_tie(v, w))
+        })
+    }
+
+    fn rank(self: &Self, c: Category) -> usize {
+        self.categories
+            .iter()
+            .position(|&d| d == c)
+            .unwrap_or(self.categories.len())
+    }
+
+    fn directed(self: &Self, ordering: Ordering) -> Ordering {
+        match self.direction {
+            Direction::Ascending => ordering,
+            Direction::Descending => ordering.reverse(),
+        }
+    }
+
+    fn break_tie(self: &Self, v: &Item, w: &Item) -> Ordering {
+        self.tie_breakers
+            .iter()
+            .map(|t| match t {
+                TieBreaker::Title => v.title.cmp(&w.title),
+                TieBreaker::DateAdded => self.directed(v.added_at.cmp(&w.added_at)),
+            })
+            .find(|&o| o != Ordering::Equal)
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+impl FromStr for Category {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        parse_name(s)
+    }
+}
+
+impl FromStr for Direction {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        parse_name(s)
+    }
+}
+
+impl FromStr for TieBreaker {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        parse_name(s)
+    }
+}
+
+/// parse_name parses the name of an enum variant in the same way as a config file.
+fn parse_name<T: DeserializeOwned>(s: &str) -> std::result::Result<T, String> {
+    let deserializer: value::StrDeserializer<value::Error> = s.into_deserializer();
+    T::deserialize(deserializer).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::new_item;
+    use super::*;
+
+    #[test]
+    fn default_policy_is_valid() {
+        assert_eq!(SortPolicy::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn validate_rejects_missing_and_repeated_categories() {
+        let mut policy = SortPolicy::default();
+        policy.categories.pop();
+        assert!(policy.validate().is_err());
+
+        policy.categories.push(Category::Viewable);
+        assert!(policy.validate().is_err());
+    }
+
+    #[test]
+    fn parse_names() {
+        assert_eq!("scheduled".parse(), Ok(Category::Scheduled));
+        assert_eq!("ascending".parse(), Ok(Direction::Ascending));
+        assert_eq!("date-added".parse(), Ok(TieBreaker::DateAdded));
+        assert!("newest".parse::<Direction>().is_err());
+    }
+
+    #[test]
+    fn custom_category_order_and_direction() {
+        let policy = SortPolicy {
+            categories: vec![
+                Category::Scheduled,
+                Category::Blocked,
+                Category::Viewable,
+                Category::Invalid,
+            ],
+            direction: Direction::Ascending,
+            tie_breakers: vec![],
+        };
+        let mut items = vec![
+            dated_item(1, None, None, false),
+            dated_item(2, Some(2), None, false),
+            dated_item(3, Some(1), None, false),
+            dated_item(4, Some(3), None, true),
+            dated_item(5, None, Some(1), false),
+        ];
+        items.sort_by(|v, w| policy.compare(v, w));
+        assert_eq!(video_ids(&items), vec!["v5", "v4", "v3", "v2", "v1"]);
+    }
+
+    #[test]
+    fn tie_breakers_are_applied_in_turn() {
+        let mut policy = SortPolicy::default();
+        policy.tie_breakers = vec![TieBreaker::DateAdded, TieBreaker::Title];
+        let mut items = vec![
+            dated_item(1, None, None, false),
+            dated_item(2, None, None, false),
+            dated_item(3, None, None, false),
+        ];
+        items[0].title = "b".to_string();
+        items[1].title = "a".to_string();
+        items[2].added_at = Some("2021-10-01T00:00:00Z".parse().unwrap());
+        items.sort_by(|v, w| policy.compare(v, w));
+        assert_eq!(video_ids(&items), vec!["v3", "v2", "v1"]);
+    }
+
+    /// dated_item returns item n, published or scheduled on the given days of September 2021.
+    fn dated_item(n: u32, published: Option<u32>, scheduled: Option<u32>, blocked: bool) -> Item {
+        let day = |d| format!("2021-09-{:02}T12:00:00Z", d).parse().unwrap();
+        let mut i = new_item(n);
+        i.video_published_at = published.map(day);
+        i.scheduled_start_time = scheduled.map(day);
+        i.blocked = blocked;
+        i
+    }
+
+    fn video_ids(items: &[Item]) -> Vec<&str> {
+        items.iter().map(|i| i.video_id.as_str()).collect()
+    }
+}
@@ -0,0 +1,48 @@
+use super::backend::PlaylistBackend;
+use super::output::OutputFormat;
+use super::playlist::{self, Item, Playlist};
+use std::future::Future;
+
+/// PLAYLIST_ID is the id of the playlist which tests manipulate.
+pub const PLAYLIST_ID: &str = "pl1";
+
+/// block_on runs the given future to completion on a single-threaded runtime. Timers are enabled so that
+/// retries can wait.
+pub fn block_on<F: Future>(f: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+        .unwrap()
+        .block_on(f)
+}
+
+/// new_playlist constructs a Playlist for the playlist PLAYLIST_ID using the given backend, with no timezone,
+/// debugging, or journal, and reporting in text. If dry_run is true, the playlist is not changed.
+pub fn new_playlist<B: PlaylistBackend>(backend: B, dry_run: bool) -> impl Playlist {
+    playlist::new(
+        backend,
+        PLAYLIST_ID,
+        "".to_string(),
+        dry_run,
+        false,
+        OutputFormat::Text,
+        None,
+    )
+    .unwrap()
+}
+
+/// new_item returns an item, with no time information, of the video with id "v<n>" and title "video <n>",
+/// whose playlist item id is "pii<n>".
+pub fn new_item(n: u32) -> Item {
+    Item {
+        video_id: format!("v{}", n),
+        playlist_item_id: format!("pii{}", n),
+        title: format!("video {}", n),
+        ..Default::default()
+    }
+}
+
+/// new_items returns an item, as returned by new_item, for each of the given numbers, in order.
+pub fn new_items(ns: &[u32]) -> Vec<Item> {
+    ns.iter().map(|n| new_item(*n)).collect()
+}
@@ -0,0 +1,345 @@
+use super::encryption::{Encryption, KEY_VARIABLE, PASSPHRASE_VARIABLE};
+use super::error::{Error, Result};
+use async_trait::async_trait;
+use log::debug;
+use serde::Deserialize;
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use yup_oauth2::storage::{TokenInfo, TokenStorage};
+
+/// LEGACY_TOKEN_CACHE is the file, in the current directory, in which earlier versions of the application cached
+/// tokens.
+pub const LEGACY_TOKEN_CACHE: &str = "playlist-manager-tokencache.json";
+
+/// TokenCache stores OAuth tokens, keyed by their scopes, in a JSON file, which is encrypted if the cache has
+/// an Encryption. Clones share the same tokens, so several authenticators can use one cache and a token
+/// obtained, or refreshed, by one is used by the others.
+#[derive(Clone)]
+pub struct TokenCache {
+    path: PathBuf,
+    tokens: Arc<Mutex<BTreeMap<String, TokenInfo>>>,
+    encryption: Option<Encryption>,
+}
+
+impl TokenCache {
+    /// load reads the tokens in the given file, decrypting them with the given encryption if the file is
+    /// encrypted. A plaintext file is encrypted straight away if there is an encryption. The cache starts empty
+    /// if the file does not exist. A file written by an earlier version of the application is read too, and is
+    /// rewritten in the current format when tokens are next saved. An error is returned if the file is
+    /// encrypted and cannot be decrypted, or cannot be parsed, so that it is not overwritten.
+    pub fn load(path: &Path, encryption: Option<Encryption>) -> Result<TokenCache> {
+        let (json, plaintext) = match fs::read_to_string(path) {
+            Ok(data) if Encryption::is_encrypted(&data) => match &encryption {
+                Some(encryption) => (encryption.decrypt(&data)?, false),
+                None => {
+                    return Err(Error::Auth(format!(
+                        "the token cache {} is encrypted: set {} or {}",
+                        path.display(),
+                        KEY_VARIABLE,
+                        PASSPHRASE_VARIABLE
+                    )))
+                }
+            },
+            Ok(data) => (data.into_bytes(), true),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (b"{}".to_vec(), false),
+            Err(e) => return Err(e.into()),
+        };
+        let tokens: BTreeMap<String, TokenInfo> = match serde_json::from_slice(&json) {
+            Ok(tokens) => tokens,
+            Err(e) => match serde_json::from_slice::<Vec<LegacyToken>>(&json) {
+                Ok(legacy_tokens) => {
+                    debug!(
+                        "reading token cache {} in the legacy format",
+                        path.display()
+                    );
+                    legacy_tokens.into_iter().map(LegacyToken::entry).collect()
+                }
+                Err(_) => {
+                    return Err(Error::Auth(format!(
+                        "the token cache {} cannot be read, so delete it and run auth login: {}",
+                        path.display(),
+                        e
+                    )))
+                }
+            },
+        };
+        let cache = TokenCache {
+            path: path.to_owned(),
+            tokens: Arc::new(Mutex::new(tokens)),
+            encryption: encryption,
+        };
+        if plaintext && cache.encryption.is_some() {
+            cache.save(&cache.tokens.lock().unwrap())?;
+            eprintln!("The token cache {} has been encrypted.", path.display());
+        }
+        Ok(cache)
+    }
+
+    /// import moves the tokens in the given token cache, which was written by an earlier version of the
+    /// application, into this cache, unless this cache already has tokens for the same scopes, and deletes the
+    /// given file. It returns true if and only if the file existed and was imported. A file which cannot be
+    /// parsed, or which is this cache's own file, is left alone.
+    pub fn import(self: &Self, legacy: &Path) -> Result<bool> {
+        if let (Ok(legacy), Ok(path)) = (fs::canonicalize(legacy), fs::canonicalize(&self.path)) {
+            if legacy == path {
+                return Ok(false);
+            }
+        }
+        let data = match fs::read(legacy) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+        let legacy_tokens: Vec<LegacyToken> = match serde_json::from_slice(&data) {
+            Ok(legacy_tokens) => legacy_tokens,
+            Err(e) => {
+                eprintln!(
+                    "Warning: couldn't read the tokens cached by an earlier version of the application in {}, \
+                     which should be deleted: {}",
+                    legacy.display(),
+                    e
+                );
+                return Ok(false);
+            }
+        };
+        {
+            let mut tokens = self.tokens.lock().unwrap();
+            for t in legacy_tokens {
+                let (key, token) = t.entry();
+                tokens.entry(key).or_insert(token);
+            }
+            self.save(&tokens)?;
+        }
+        fs::remove_file(legacy)?;
+        eprintln!(
+            "The tokens cached in {} have been moved to {}.",
+            legacy.display(),
+            self.path.display()
+        );
+        Ok(true)
+    }
+
+    /// scopes returns the scopes for which tokens are cached, in alphabetical order.
+    pub fn scopes(self: &Self) -> Vec<String> {
+        let tokens = self.tokens.lock().unwrap();
+        let scopes: BTreeSet<&str> = tokens.keys().flat_map(|k| k.split(' ')).collect();
+        scopes.into_iter().map(|s| s.to_string()).collect()
+    }
+
+    /// save writes the given tokens to the cache's file, which only its owner may read. The file is replaced in a
+    /// single step so that an interruption cannot leave it partly written.
+    fn save(self: &Self, tokens: &BTreeMap<String, TokenInfo>) -> Result<()> {
+        let json = serde_json::to_string_pretty(tokens)
+            .map_err(|e| Error::BadInput(format!("couldn't serialize tokens: {}", e)))?;
+        let data = match &self.encryption {
+            Some(encryption) => encryption.encrypt(json.as_bytes())?,
+            None => json,
+        };
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut partial = self.path.as_os_str().to_owned();
+        partial.push(".partial");
+        // A partial file left by an earlier interruption may have been created with other permissions.
+        match fs::remove_file(&partial) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let mut file = options.open(&partial)?;
+        file.write_all(data.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&partial, &self.path)?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TokenStorage for TokenCache {
+    async fn set(&self, scopes: &[&str], token: TokenInfo) -> anyhow::Result<()> {
+        let mut tokens = self.tokens.lock().unwrap();
+        tokens.insert(key(scopes), token);
+        self.save(&tokens)
+            .map_err(|e| anyhow::anyhow!("couldn't save tokens: {}", e))
+    }
+
+    async fn get(&self, scopes: &[&str]) -> Option<TokenInfo> {
+        self.tokens.lock().unwrap().get(&key(scopes)).cloned()
+    }
+}
+
+/// LegacyToken is a token, together with its scopes, as cached by earlier versions of the application.
+#[derive(Deserialize)]
+struct LegacyToken {
+    scopes: Vec<String>,
+    token: TokenInfo,
+}
+
+impl LegacyToken {
+    /// entry returns the key and the token under which the token is cached.
+    fn entry(self: Self) -> (String, TokenInfo) {
+        let scopes: Vec<&str> = self.scopes.iter().map(|s| s.as_str()).collect();
+        (key(&scopes), self.token)
+    }
+}
+
+/// key returns the key of the token for the given scopes, which does not depend on their order.
+fn key(scopes: &[&str]) -> String {
+    let mut scopes = scopes.to_vec();
+    scopes.sort_unstable();
+    scopes.dedup();
+    scopes.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::block_on;
+    use super::*;
+
+    #[test]
+    fn tokens_are_shared_and_saved() {
+        let path = std::env::temp_dir().join(format!("tokens-test-{}.json", std::process::id()));
+        let cache = TokenCache::load(&path, None).unwrap();
+        let clone = cache.clone();
+        block_on(cache.set(&["b", "a"], new_token("t1"))).unwrap();
+        assert_eq!(block_on(clone.get(&["a", "b"])), Some(new_token("t1")));
+        assert_eq!(block_on(clone.get(&["a"])), None);
+        assert_eq!(clone.scopes(), vec!["a", "b"]);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let loaded = TokenCache::load(&path, None);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            block_on(loaded.unwrap().get(&["a", "b", "a"])),
+            Some(new_token("t1"))
+        );
+    }
+
+    #[test]
+    fn unreadable_cache_is_not_replaced() {
+        let path =
+            std::env::temp_dir().join(format!("bad-tokens-test-{}.json", std::process::id()));
+        fs::write(&path, "not json").unwrap();
+        let cache = TokenCache::load(&path, None);
+        let data = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(cache, Err(Error::Auth(_))));
+        assert_eq!(data, "not json");
+    }
+
+    #[test]
+    fn legacy_cache_given_as_the_cache_is_kept() {
+        let path =
+            std::env::temp_dir().join(format!("legacy-cache-test-{}.json", std::process::id()));
+        fs::write(
+            &path,
+            serde_json::json!([{"scopes": ["a"], "token": new_token("t1")}]).to_string(),
+        )
+        .unwrap();
+        let cache = TokenCache::load(&path, None).unwrap();
+        let imported = cache.import(&path).unwrap();
+        let exists = path.exists();
+        let reloaded = TokenCache::load(&path, None);
+        fs::remove_file(&path).unwrap();
+
+        assert!(!imported && exists);
+        assert_eq!(block_on(cache.get(&["a"])), Some(new_token("t1")));
+        assert_eq!(
+            block_on(reloaded.unwrap().get(&["a"])),
+            Some(new_token("t1"))
+        );
+    }
+
+    #[test]
+    fn plaintext_cache_is_encrypted() {
+        let path =
+            std::env::temp_dir().join(format!("plaintext-tokens-test-{}.json", std::process::id()));
+        let cache = TokenCache::load(&path, None).unwrap();
+        block_on(cache.set(&["a"], new_token("t1"))).unwrap();
+
+        let encryption = Encryption::with_key(KEY).unwrap();
+        let migrated = TokenCache::load(&path, Some(encryption.clone())).unwrap();
+        let data = fs::read_to_string(&path).unwrap();
+        block_on(migrated.set(&["b"], new_token("t2"))).unwrap();
+        let unencrypted = TokenCache::load(&path, None);
+        let reloaded = TokenCache::load(&path, Some(encryption));
+        fs::remove_file(&path).unwrap();
+
+        assert!(Encryption::is_encrypted(&data) && !data.contains("access_token"));
+        assert_eq!(block_on(migrated.get(&["a"])), Some(new_token("t1")));
+        assert!(matches!(unencrypted, Err(Error::Auth(_))));
+        let reloaded = reloaded.unwrap();
+        assert_eq!(block_on(reloaded.get(&["a"])), Some(new_token("t1")));
+        assert_eq!(block_on(reloaded.get(&["b"])), Some(new_token("t2")));
+    }
+
+    #[test]
+    fn legacy_cache_is_imported_and_deleted() {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("imported-tokens-test-{}.json", std::process::id()));
+        let legacy = dir.join(format!("legacy-tokens-test-{}.json", std::process::id()));
+        let cache = TokenCache::load(&path, Some(Encryption::with_key(KEY).unwrap())).unwrap();
+        block_on(cache.set(&["a"], new_token("t1"))).unwrap();
+        fs::write(
+            &legacy,
+            serde_json::json!([
+                {"scopes": ["a"], "token": new_token("old")},
+                {"scopes": ["c", "b"], "token": new_token("t2")}
+            ])
+            .to_string(),
+        )
+        .unwrap();
+
+        let imported = cache.import(&legacy).unwrap();
+        let legacy_exists = legacy.exists();
+        let data = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(imported && !legacy_exists);
+        assert!(Encryption::is_encrypted(&data) && !data.contains("access_token"));
+        assert_eq!(block_on(cache.get(&["a"])), Some(new_token("t1")));
+        assert_eq!(block_on(cache.get(&["b", "c"])), Some(new_token("t2")));
+        assert_eq!(cache.import(&legacy).unwrap(), false);
+    }
+
+    #[test]
+    fn unreadable_legacy_cache_is_left_alone() {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("kept-tokens-test-{}.json", std::process::id()));
+        let legacy = dir.join(format!(
+            "bad-legacy-tokens-test-{}.json",
+            std::process::id()
+        ));
+        fs::write(&legacy, "{}").unwrap();
+        let imported = TokenCache::load(&path, None).unwrap().import(&legacy);
+        let legacy_exists = legacy.exists();
+        fs::remove_file(&legacy).unwrap();
+        assert!(!imported.unwrap() && legacy_exists && !path.exists());
+    }
+
+    const KEY: &str = "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=";
+
+    fn new_token(access_token: &str) -> TokenInfo {
+        serde_json::from_value(serde_json::json!({
+            "access_token": access_token,
+            "refresh_token": "refresh"
+        }))
+        .unwrap()
+    }
+}
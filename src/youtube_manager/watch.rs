@@ -0,0 +1,197 @@
+use super::config::Config;
+use super::error::Result;
+use super::plan::{Deletion, Plan};
+use super::playlist::{Item, Playlist};
+use super::reorder::Move;
+use std::fmt;
+
+/// Watcher sorts, and optionally prunes, a playlist whenever it needs it. It is driven by calling cycle
+/// periodically.
+pub struct Watcher {
+    config: Config,
+    prune: bool,
+    /// dry_run is true if the playlist being watched only reports changes rather than making them.
+    dry_run: bool,
+    /// last is the playlist item ids, in playlist order, at the end of the previous cycle, together with the
+    /// changes planned in that cycle.
+    last: Option<(Vec<String>, Changes)>,
+}
+
+/// Changes are the deletions and moves of a plan.
+type Changes = (Vec<Deletion>, Vec<Move>);
+
+/// Cycle is the outcome of one cycle of a Watcher.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Cycle {
+    /// Unchanged indicates that the playlist had not changed since the previous cycle and needed no changes
+    /// other than those planned in the previous cycle, so nothing was done.
+    Unchanged,
+    /// InOrder indicates that the playlist had changed, but was already sorted and pruned.
+    InOrder,
+    /// Updated indicates that a plan with the given number of API writes was applied to the playlist.
+    Updated { writes: usize },
+    /// Planned indicates that, in dry-run mode, a plan with the given number of API writes was reported but
+    /// not applied to the playlist.
+    Planned { writes: usize },
+}
+
+impl fmt::Display for Cycle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cycle::Unchanged => write!(f, "playlist unchanged"),
+            Cycle::InOrder => write!(f, "playlist changed, but is already in order"),
+            Cycle::Updated { writes } => {
+                write!(f, "playlist was updated ({} API write(s))", writes)
+            }
+            Cycle::Planned { writes } => write!(
+                f,
+                "playlist changes were planned, but not made, in this dry run ({} API write(s))",
+                writes
+            ),
+        }
+    }
+}
+
+impl Watcher {
+    /// new constructs a Watcher which sorts playlists according to the given config and, if prune is true,
+    /// prunes them. dry_run must be true if the playlists are in dry-run mode, so that cycles report the
+    /// changes as planned rather than made.
+    pub fn new(config: Config, prune: bool, dry_run: bool) -> Watcher {
+        Watcher {
+            config: config,
+            prune: prune,
+            dry_run: dry_run,
+            last: None,
+        }
+    }
+
+    /// cycle sorts, and optionally prunes, the given playlist whenever that needs any changes. The videos are
+    /// looked up each time, so that changes to them, such as a scheduled stream starting, are acted on even
+    /// if the playlist's items are the same as at the end of the previous cycle. However, changes which were
+    /// planned, but evidently not made, in the previous cycle, as in dry-run mode, are not planned again.
+    pub async fn cycle<P: Playlist>(self: &mut Self, playlist: &P) -> Result<Cycle> {
+        let plan = playlist.plan(&self.config, self.prune).await?;
+        let ids = playlist_item_ids(&plan.items);
+        let changes = changes(&plan);
+        let writes = plan.writes();
+        if let Some((last_ids, last_changes)) = &self.last {
+            if *last_ids == ids && (writes == 0 || *last_changes == changes) {
+                return Ok(Cycle::Unchanged);
+            }
+        }
+
+        if writes == 0 {
+            self.last = Some((ids, changes));
+            return Ok(Cycle::InOrder);
+        }
+
+        playlist.apply(&plan).await?;
+        // Read the playlist back rather than relying on the plan's result, which is not reached in dry-run mode.
+        self.last = Some((playlist_item_ids(&playlist.items().await?), changes));
+        if self.dry_run {
+            Ok(Cycle::Planned { writes: writes })
+        } else {
+            Ok(Cycle::Updated { writes: writes })
+        }
+    }
+}
+
+fn changes(plan: &Plan) -> Changes {
+    (plan.deletions.clone(), plan.moves.clone())
+}
+
+fn playlist_item_ids(items: &[Item]) -> Vec<String> {
+    items.iter().map(|i| i.playlist_item_id.clone()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::memory::InMemoryBackend;
+    use super::super::testing::{block_on, new_playlist, PLAYLIST_ID};
+    use super::*;
+    use google_youtube3::api::{Video, VideoLiveStreamingDetails};
+
+    #[test]
+    fn cycle_updates_only_changed_playlists() {
+        let backend = InMemoryBackend::new();
+        backend.add_item(PLAYLIST_ID, "v1", "video 1", Some(published(1)));
+        backend.add_item(PLAYLIST_ID, "v2", "video 2", Some(published(2)));
+        let playlist = new_playlist(backend.clone(), false);
+        let mut watcher = Watcher::new(Default::default(), false, false);
+
+        assert_eq!(
+            block_on(watcher.cycle(&playlist)).unwrap(),
+            Cycle::Updated { writes: 1 }
+        );
+        assert_eq!(backend.video_ids(PLAYLIST_ID), vec!["v2", "v1"]);
+        assert_eq!(
+            block_on(watcher.cycle(&playlist)).unwrap(),
+            Cycle::Unchanged
+        );
+
+        backend.add_item(PLAYLIST_ID, "v3", "video 3", None);
+        assert_eq!(block_on(watcher.cycle(&playlist)).unwrap(), Cycle::InOrder);
+        assert_eq!(
+            block_on(watcher.cycle(&playlist)).unwrap(),
+            Cycle::Unchanged
+        );
+        assert_eq!(backend.position_updates(), 1);
+    }
+
+    #[test]
+    fn cycle_acts_on_video_changes_in_an_unchanged_playlist() {
+        let backend = InMemoryBackend::new();
+        backend.add_item(PLAYLIST_ID, "v1", "video 1", Some(published(2)));
+        backend.add_item(PLAYLIST_ID, "v2", "video 2", None);
+        backend.add_video(stream("v2", None));
+        let playlist = new_playlist(backend.clone(), false);
+        let mut watcher = Watcher::new(Default::default(), false, false);
+        assert_eq!(block_on(watcher.cycle(&playlist)).unwrap(), Cycle::InOrder);
+
+        // the scheduled stream starts
+        backend.add_video(stream("v2", Some(published(3))));
+        assert_eq!(
+            block_on(watcher.cycle(&playlist)).unwrap(),
+            Cycle::Updated { writes: 1 }
+        );
+        assert_eq!(backend.video_ids(PLAYLIST_ID), vec!["v2", "v1"]);
+        assert_eq!(
+            block_on(watcher.cycle(&playlist)).unwrap(),
+            Cycle::Unchanged
+        );
+    }
+
+    #[test]
+    fn dry_run_cycles_report_changes_once() {
+        let backend = InMemoryBackend::new();
+        backend.add_item(PLAYLIST_ID, "v1", "video 1", Some(published(1)));
+        backend.add_item(PLAYLIST_ID, "v2", "video 2", Some(published(2)));
+        let playlist = new_playlist(backend.clone(), true);
+        let mut watcher = Watcher::new(Default::default(), true, true);
+
+        let cycle = block_on(watcher.cycle(&playlist)).unwrap();
+        assert_eq!(cycle, Cycle::Planned { writes: 1 });
+        assert!(!cycle.to_string().contains("updated"));
+        assert_eq!(
+            block_on(watcher.cycle(&playlist)).unwrap(),
+            Cycle::Unchanged
+        );
+        assert_eq!(backend.video_ids(PLAYLIST_ID), vec!["v1", "v2"]);
+    }
+
+    fn stream(video_id: &str, actual_start_time: Option<String>) -> Video {
+        Video {
+            id: Some(video_id.to_string()),
+            live_streaming_details: Some(VideoLiveStreamingDetails {
+                scheduled_start_time: Some(published(1)),
+                actual_start_time: actual_start_time,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn published(day: u32) -> String {
+        format!("2021-09-{:02}T12:00:00Z", day)
+    }
+}
@@ -0,0 +1,199 @@
+use super::backend::{PlaylistBackend, MAX_VIDEO_IDS};
+use super::error::{Error, Result};
+use async_trait::async_trait;
+use google_youtube3::{
+    api::Scope,
+    api::{PlaylistItem, PlaylistItemListResponse, PlaylistItemSnippet, ResourceId, Video},
+    client, YouTube,
+};
+use hyper::{
+    client::HttpConnector,
+    header::{AUTHORIZATION, IF_NONE_MATCH, USER_AGENT},
+    Body, Request, StatusCode,
+};
+use hyper_rustls::HttpsConnector;
+use log::debug;
+use url::form_urlencoded;
+use yup_oauth2::authenticator::Authenticator;
+
+/// PLAYLIST_ITEMS_URL is the YouTube Data API endpoint for listing playlist items.
+const PLAYLIST_ITEMS_URL: &str = "https://youtube.googleapis.com/youtube/v3/playlistItems";
+
+struct YouTubeBackend {
+    hub: YouTube,
+    client: hyper::Client<HttpsConnector<HttpConnector>>,
+    auth: Authenticator<HttpsConnector<HttpConnector>>,
+    scope: Scope,
+}
+
+/// new constructs a PlaylistBackend implementation which uses the given YouTube hub to call the YouTube Data API.
+/// Playlist items are listed using the given client and authenticator instead, because the hub cannot make
+/// conditional requests. The authenticator should share its token storage with the hub's authenticator.
+///
+/// Every call is authorised for the given scope, so that only one token is needed: Scope::Readonly if the
+/// playlist is only read, or Scope::Full if it is also modified.
+pub fn new(
+    hub: YouTube,
+    client: hyper::Client<HttpsConnector<HttpConnector>>,
+    auth: Authenticator<HttpsConnector<HttpConnector>>,
+    scope: Scope,
+) -> impl PlaylistBackend {
+    YouTubeBackend {
+        hub: hub,
+        client: client,
+        auth: auth,
+        scope: scope,
+    }
+}
+
+#[async_trait]
+impl PlaylistBackend for YouTubeBackend {
+    async fn list_items(
+        self: &Self,
+        playlist_id: &str,
+        page_token: &Option<String>,
+        etag: Option<&str>,
+    ) -> Result<Option<PlaylistItemListResponse>> {
+        debug!("listing playlist items");
+        let token = self
+            .auth
+            .token(&[self.scope.as_ref()])
+            .await
+            .map_err(|e| Error::Auth(e.to_string()))?;
+
+        let mut query = form_urlencoded::Serializer::new(String::new());
+        query
+            .append_pair("part", "snippet,id,contentDetails")
+            .append_pair("playlistId", playlist_id)
+            .append_pair("maxResults", &MAX_VIDEO_IDS.to_string());
+        if let Some(next) = page_token {
+            query.append_pair("pageToken", next);
+        }
+        let mut req = Request::get(format!("{}?{}", PLAYLIST_ITEMS_URL, query.finish()))
+            .header(USER_AGENT, "youtube-playlist-manager")
+            .header(AUTHORIZATION, format!("Bearer {}", token.as_str()));
+        if let Some(etag) = etag {
+            req = req.header(IF_NONE_MATCH, etag);
+        }
+        let req = req
+            .body(Body::empty())
+            .map_err(|e| Error::BadInput(format!("invalid playlist items request: {}", e)))?;
+
+        let res = self
+            .client
+            .request(req)
+            .await
+            .map_err(client::Error::HttpError)?;
+        let status = res.status();
+        if status == StatusCode::NOT_MODIFIED {
+            debug!("playlist items not modified");
+            return Ok(None);
+        }
+        let body = hyper::body::to_bytes(res.into_body())
+            .await
+            .map_err(client::Error::HttpError)?;
+        let body = String::from_utf8_lossy(&body).into_owned();
+        if !status.is_success() {
+            // Classify the error in the same way as errors returned through the hub. Errors without a JSON body,
+            // e.g. from a proxy, are given one so that server errors can be retried.
+            let value = serde_json::from_str(&body).unwrap_or_else(|_| {
+                serde_json::json!({
+                    "error": {
+                        "code": status.as_u16(),
+                        "message": format!("listing playlist items failed with status {}: {}", status, body)
+                    }
+                })
+            });
+            return Err(client::Error::BadRequest(value).into());
+        }
+        match serde_json::from_str(&body) {
+            Ok(res) => Ok(Some(res)),
+            Err(e) => Err(client::Error::JsonDecodeError(body, e).into()),
+        }
+    }
+
+    async fn videos(self: &Self, video_ids: &[String]) -> Result<Vec<Video>> {
+        debug!("looking up {} videos", video_ids.len());
+        let mut req = self.hub.videos().list(&vec![
+            "liveStreamingDetails".into(),
+            "contentDetails".into(),
+        ]);
+        for video_id in video_ids {
+            req = req.add_id(video_id);
+        }
+        let (_, v) = req.add_scope(self.scope.as_ref()).doit().await?;
+        Ok(v.items.unwrap_or_default())
+    }
+
+    async fn update_position(
+        self: &Self,
+        playlist_id: &str,
+        playlist_item_id: &str,
+        video_id: &str,
+        position: u32,
+    ) -> Result<()> {
+        self.hub
+            .playlist_items()
+            .update(PlaylistItem {
+                id: Some(playlist_item_id.to_owned()),
+                snippet: Some(PlaylistItemSnippet {
+                    playlist_id: Some(playlist_id.to_owned()),
+                    resource_id: Some(ResourceId {
+                        kind: Some("youtube#video".to_owned()),
+                        video_id: Some(video_id.to_owned()),
+                        ..Default::default()
+                    }),
+                    position: Some(position),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .add_scope(self.scope.as_ref())
+            .doit()
+            .await?;
+        Ok(())
+    }
+
+    async fn insert(
+        self: &Self,
+        playlist_id: &str,
+        video_id: &str,
+        position: Option<u32>,
+    ) -> Result<String> {
+        let (_, item) = self
+            .hub
+            .playlist_items()
+            .insert(PlaylistItem {
+                snippet: Some(PlaylistItemSnippet {
+                    playlist_id: Some(playlist_id.to_owned()),
+                    resource_id: Some(ResourceId {
+                        kind: Some("youtube#video".to_owned()),
+                        video_id: Some(video_id.to_owned()),
+                        ..Default::default()
+                    }),
+                    position: position,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .add_scope(self.scope.as_ref())
+            .doit()
+            .await?;
+        item.id.ok_or_else(|| {
+            Error::MalformedData(format!(
+                "no playlist item id returned for video {} inserted into playlist {}",
+                video_id, playlist_id
+            ))
+        })
+    }
+
+    async fn delete(self: &Self, playlist_item_id: &str) -> Result<()> {
+        self.hub
+            .playlist_items()
+            .delete(playlist_item_id)
+            .add_scope(self.scope.as_ref())
+            .doit()
+            .await?;
+        Ok(())
+    }
+}